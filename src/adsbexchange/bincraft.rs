@@ -1,7 +1,107 @@
 use super::util::{
-    as_other_array, convert_char_array_to_string, get_navmodes_from_num, get_track_type_from_num,
+    convert_char_array_to_string, get_navmodes_from_num, get_track_type_from_num, ByteView,
 };
 use radix_fmt::radix;
+use std::fmt::Display;
+use std::io::Read;
+use std::ops::Range;
+
+// Size of the fixed part of the header (time, stride, count, index and limits)
+const HEADER_SIZE: usize = 28;
+// Index of the u32 binCraftVersion in the header, written by newer readsb builds
+const HEADER_VERSION_INDEX: usize = 10;
+// Frame magic of zstd compressed tiles (.binCraft.zst)
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+// Upper bound for a decompressed tile
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Field layout of one binCraft version
+/// https://github.com/wiedehopf/tar1090/blob/master/html/formatter.js
+#[derive(Debug)]
+struct Layout {
+    stride: usize,
+    versions: Range<u32>,
+    // seen_pos and seen are 32 bit, pushing every following field back by 4 bytes
+    wide_seen: bool,
+    // dbFlags is 16 bit, taking the place of the old signal byte
+    wide_db_flags: bool,
+    // Offset of the signal byte, not counting the shift from wide_seen
+    rssi: usize,
+}
+
+const LAYOUTS: [Layout; 3] = [
+    // Original layout, no version in the header
+    Layout {
+        stride: 112,
+        versions: 0..1,
+        wide_seen: false,
+        wide_db_flags: false,
+        rssi: 86,
+    },
+    Layout {
+        stride: 112,
+        versions: 1..20220916,
+        wide_seen: false,
+        wide_db_flags: true,
+        rssi: 105,
+    },
+    Layout {
+        stride: 116,
+        versions: 20220916..u32::MAX,
+        wide_seen: true,
+        wide_db_flags: true,
+        rssi: 105,
+    },
+];
+
+#[derive(Debug)]
+pub enum DecodeError {
+    ShortHeader {
+        len: usize,
+        needed: usize,
+    },
+    UnknownStride(usize),
+    UnknownVersion {
+        stride: usize,
+        version: u32,
+    },
+    Decompress(std::io::Error),
+    TruncatedRecord {
+        offset: usize,
+        stride: usize,
+        len: usize,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ShortHeader { len, needed } => write!(
+                f,
+                "buffer of {} bytes is too short for a {} byte header",
+                len, needed
+            ),
+            Self::UnknownStride(stride) => write!(f, "unknown record stride {}", stride),
+            Self::UnknownVersion { stride, version } => write!(
+                f,
+                "unknown binCraft version {} with record stride {}",
+                version, stride
+            ),
+            Self::Decompress(e) => write!(f, "could not decompress zstd payload: {}", e),
+            Self::TruncatedRecord {
+                offset,
+                stride,
+                len,
+            } => write!(
+                f,
+                "record at offset {} needs {} bytes but only {} remain",
+                offset,
+                stride,
+                len - offset
+            ),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum NavModes {
@@ -40,13 +140,12 @@ pub struct BoundingLimits {
 }
 
 impl BoundingLimits {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let bytes_i16: &[i16] = as_other_array(bytes, 4);
+    fn from_header(header: &ByteView) -> Self {
         Self {
-            south: bytes_i16[0],
-            west: bytes_i16[1],
-            north: bytes_i16[2],
-            east: bytes_i16[3],
+            south: header.s16(10),
+            west: header.s16(11),
+            north: header.s16(12),
+            east: header.s16(13),
         }
     }
 }
@@ -54,8 +153,8 @@ impl BoundingLimits {
 #[derive(Debug)]
 pub struct ADSBExData {
     pub hex: String,
-    pub last_pos: Option<u16>,
-    pub last_seen: u16,
+    pub last_pos: Option<u32>,
+    pub last_seen: u32,
 
     pub lat: Option<f64>,
     pub lon: Option<f64>,
@@ -118,7 +217,7 @@ pub struct ADSBExData {
     pub flight: Option<String>,
 
     pub rssi: f64,
-    pub db_flags: u8,
+    pub db_flags: u16,
     pub aircraft_type: String,
     pub registration: String,
     pub receiver_count: u8,
@@ -130,237 +229,258 @@ pub struct ADSBExData {
 }
 
 impl ADSBExData {
-    pub fn from_bytes(bytes: &[u8], stride: usize, time: f64) -> Self {
+    /// `raw` must be exactly one record of `layout.stride` bytes
+    fn from_record(raw: &ByteView, layout: &Layout, time: f64) -> Self {
         // https://github.com/wiedehopf/tar1090/blob/a74ea50524123fd90c9ba6da351b3f7e1385b60d/html/formatter.js#L343
-        let s32: &[i32] = as_other_array(bytes, stride / 4);
-        let ua16: &[u16] = as_other_array(bytes, stride / 2);
-        let s16: &[i16] = as_other_array(bytes, stride / 2);
-        let ua8: &[u8] = &bytes[0..stride];
 
         // Hex
-        let mut hex = format!("{:x}", s32[0] & ((1 << 24) - 1)).to_uppercase();
-        if (s32[0] & (1 << 24)) & (1 << 24) != 0 {
+        let mut hex = format!("{:x}", raw.s32(0) & ((1 << 24) - 1)).to_uppercase();
+        if (raw.s32(0) & (1 << 24)) & (1 << 24) != 0 {
             hex = "~".to_owned() + &hex;
         }
 
-        let callsign = convert_char_array_to_string(&ua8[78..86]);
-        let aircraft_type = convert_char_array_to_string(&ua8[88..92]);
-        let registration = convert_char_array_to_string(&ua8[92..104]);
+        let (seen_pos, seen) = if layout.wide_seen {
+            (raw.u32(1), raw.u32(2))
+        } else {
+            (raw.u16(2) as u32, raw.u16(3) as u32)
+        };
+
+        // Past the seen times, fields only differ by this shift between versions
+        let record = if layout.wide_seen {
+            raw.slice(4..layout.stride)
+        } else {
+            raw.slice(0..layout.stride)
+        };
+
+        let db_flags = if layout.wide_db_flags {
+            record.u16(43)
+        } else {
+            record.u8(87) as u16
+        };
+        let signal = record.u8(layout.rssi) as f64;
+
+        let callsign = convert_char_array_to_string(record.bytes(78..86));
+        let aircraft_type = convert_char_array_to_string(record.bytes(88..92));
+        let registration = convert_char_array_to_string(record.bytes(92..104));
 
-        let category = radix(ua8[64] as i32, 16).to_string().to_uppercase();
-        let squawk = format!("{:0>4}", radix(ua16[16] as i32, 16));
+        let category = radix(record.u8(64) as i32, 16).to_string().to_uppercase();
+        let squawk = format!("{:0>4}", radix(record.u16(16) as i32, 16));
 
         // Get nav modes
-        let nav_modes = if ua8[77] & 4 != 0 {
-            Some(get_navmodes_from_num(ua8[66]))
+        let nav_modes = if record.u8(77) & 4 != 0 {
+            Some(get_navmodes_from_num(record.u8(66)))
         } else {
             None
         };
-        let track_type = get_track_type_from_num((ua8[67] & 240) >> 4);
+        let track_type = get_track_type_from_num((record.u8(67) & 240) >> 4);
 
         Self {
-            last_pos: if ua8[73] & 64 != 0 {
-                Some(ua16[2] / 10)
+            last_pos: if record.u8(73) & 64 != 0 {
+                Some(seen_pos / 10)
             } else {
                 None
             },
-            last_seen: ua16[3] / 10,
-            lat: if ua8[73] & 64 != 0 {
-                Some(s32[3] as f64 / 1e6)
+            last_seen: seen / 10,
+            lat: if record.u8(73) & 64 != 0 {
+                Some(record.s32(3) as f64 / 1e6)
             } else {
                 None
             },
-            lon: if ua8[73] & 64 != 0 {
-                Some(s32[2] as f64 / 1e6)
+            lon: if record.u8(73) & 64 != 0 {
+                Some(record.s32(2) as f64 / 1e6)
             } else {
                 None
             },
 
-            baro_rate: if ua8[75] & 1 != 0 {
-                Some(s16[8] as f64 * 8.0)
+            baro_rate: if record.u8(75) & 1 != 0 {
+                Some(record.s16(8) as f64 * 8.0)
             } else {
                 None
             },
-            geom_rate: if ua8[75] & 2 != 0 {
-                Some(s16[9] as f64 * 8.0)
+            geom_rate: if record.u8(75) & 2 != 0 {
+                Some(record.s16(9) as f64 * 8.0)
             } else {
                 None
             },
-            alt_baro: if ua8[73] & 16 != 0 {
-                Some(s16[10] as f64 * 25.0)
+            alt_baro: if record.u8(73) & 16 != 0 {
+                Some(record.s16(10) as f64 * 25.0)
             } else {
                 None
             },
-            alt_geom: if ua8[73] & 32 != 0 {
-                Some(s16[11] as f64 * 25.0)
+            alt_geom: if record.u8(73) & 32 != 0 {
+                Some(record.s16(11) as f64 * 25.0)
             } else {
                 None
             },
-            nav_altitude_mcp: if ua8[76] & 64 != 0 {
-                Some(ua16[12] as u32 * 4)
+            nav_altitude_mcp: if record.u8(76) & 64 != 0 {
+                Some(record.u16(12) as u32 * 4)
             } else {
                 None
             },
-            nav_altitude_fms: if ua8[76] & 128 != 0 {
-                Some(ua16[13] as u32 * 4)
+            nav_altitude_fms: if record.u8(76) & 128 != 0 {
+                Some(record.u16(13) as u32 * 4)
             } else {
                 None
             },
-            nav_qnh: if ua8[76] & 32 != 0 {
-                Some(s16[14] as f64 / 10.0)
+            nav_qnh: if record.u8(76) & 32 != 0 {
+                Some(record.s16(14) as f64 / 10.0)
             } else {
                 None
             },
-            nav_heading: if ua8[77] & 2 != 0 {
-                Some(s16[15] as f64 / 90.0)
+            nav_heading: if record.u8(77) & 2 != 0 {
+                Some(record.s16(15) as f64 / 90.0)
             } else {
                 None
             },
-            squawk: if ua8[76] & 4 != 0 { Some(squawk) } else { None },
-            gs: if ua8[73] & 128 != 0 {
-                Some(s16[17] as f64 / 10.0)
+            squawk: if record.u8(76) & 4 != 0 {
+                Some(squawk)
             } else {
                 None
             },
-            mach: if ua8[74] & 4 != 0 {
-                Some(s16[18] as f64 / 1000.0)
+            gs: if record.u8(73) & 128 != 0 {
+                Some(record.s16(17) as f64 / 10.0)
             } else {
                 None
             },
-            roll: if ua8[74] & 32 != 0 {
-                Some(s16[19] as f64 / 100.0)
+            mach: if record.u8(74) & 4 != 0 {
+                Some(record.s16(18) as f64 / 1000.0)
             } else {
                 None
             },
-            track: if ua8[74] & 8 != 0 {
-                Some(s16[20] as f64 / 90.0)
+            roll: if record.u8(74) & 32 != 0 {
+                Some(record.s16(19) as f64 / 100.0)
             } else {
                 None
             },
-            track_rate: if ua8[74] & 16 != 0 {
-                Some(s16[21] as f64 / 100.0)
+            track: if record.u8(74) & 8 != 0 {
+                Some(record.s16(20) as f64 / 90.0)
             } else {
                 None
             },
-            mag_heading: if ua8[74] & 64 != 0 {
-                Some(s16[22] as f64 / 90.0)
+            track_rate: if record.u8(74) & 16 != 0 {
+                Some(record.s16(21) as f64 / 100.0)
             } else {
                 None
             },
-            true_heading: if ua8[74] & 128 != 0 {
-                Some(s16[23] as f64 / 90.0)
+            mag_heading: if record.u8(74) & 64 != 0 {
+                Some(record.s16(22) as f64 / 90.0)
             } else {
                 None
             },
-            wd: if ua8[77] & 16 != 0 {
-                Some(s16[24])
+            true_heading: if record.u8(74) & 128 != 0 {
+                Some(record.s16(23) as f64 / 90.0)
             } else {
                 None
             },
-            ws: if ua8[77] & 16 != 0 {
-                Some(s16[25])
+            wd: if record.u8(77) & 16 != 0 {
+                Some(record.s16(24))
             } else {
                 None
             },
-            oat: if ua8[77] & 32 != 0 {
-                Some(s16[26])
+            ws: if record.u8(77) & 16 != 0 {
+                Some(record.s16(25))
             } else {
                 None
             },
-            tat: if ua8[77] & 32 != 0 {
-                Some(s16[27])
+            oat: if record.u8(77) & 32 != 0 {
+                Some(record.s16(26))
             } else {
                 None
             },
-            tas: if ua8[74] & 2 != 0 {
-                Some(ua16[28])
+            tat: if record.u8(77) & 32 != 0 {
+                Some(record.s16(27))
             } else {
                 None
             },
-            ias: if ua8[74] & 1 != 0 {
-                Some(ua16[29])
+            tas: if record.u8(74) & 2 != 0 {
+                Some(record.u16(28))
             } else {
                 None
             },
-            rc: ua16[30],
-            messages: ua16[31],
+            ias: if record.u8(74) & 1 != 0 {
+                Some(record.u16(29))
+            } else {
+                None
+            },
+            rc: record.u16(30),
+            messages: record.u16(31),
             category,
-            nic: ua8[65],
+            nic: record.u8(65),
             nav_modes,
-            emergency: if ua8[76] & 8 != 0 {
-                Some(ua8[67] & 15)
+            emergency: if record.u8(76) & 8 != 0 {
+                Some(record.u8(67) & 15)
             } else {
                 None
             },
             track_type,
-            airground: ua8[68] & 15,
-            nav_altitude_src: if ua8[77] & 1 != 0 {
-                Some((ua8[68] & 240) >> 4)
+            airground: record.u8(68) & 15,
+            nav_altitude_src: if record.u8(77) & 1 != 0 {
+                Some((record.u8(68) & 240) >> 4)
             } else {
                 None
             },
-            sil_type: ua8[69] & 15,
-            adsb_version: (ua8[69] & 240) >> 4,
-            adsr_version: ua8[70] & 15,
-            tisb_version: (ua8[70] & 240) >> 4,
-            nac_p: if ua8[75] & 32 != 0 {
-                Some(ua8[71] & 15)
+            sil_type: record.u8(69) & 15,
+            adsb_version: (record.u8(69) & 240) >> 4,
+            adsr_version: record.u8(70) & 15,
+            tisb_version: (record.u8(70) & 240) >> 4,
+            nac_p: if record.u8(75) & 32 != 0 {
+                Some(record.u8(71) & 15)
             } else {
                 None
             },
-            nac_v: if ua8[75] & 64 != 0 {
-                Some((ua8[71] & 240) >> 4)
+            nac_v: if record.u8(75) & 64 != 0 {
+                Some((record.u8(71) & 240) >> 4)
             } else {
                 None
             },
-            sil: if ua8[75] & 128 != 0 {
-                Some(ua8[72] & 3)
+            sil: if record.u8(75) & 128 != 0 {
+                Some(record.u8(72) & 3)
             } else {
                 None
             },
-            gva: if ua8[76] & 1 != 0 {
-                Some((ua8[72] & 12) >> 2)
+            gva: if record.u8(76) & 1 != 0 {
+                Some((record.u8(72) & 12) >> 2)
             } else {
                 None
             },
-            sda: if ua8[76] & 2 != 0 {
-                Some((ua8[72] & 48) >> 4)
+            sda: if record.u8(76) & 2 != 0 {
+                Some((record.u8(72) & 48) >> 4)
             } else {
                 None
             },
-            nic_a: if ua8[75] & 4 != 0 {
-                Some((ua8[72] & 64) >> 6)
+            nic_a: if record.u8(75) & 4 != 0 {
+                Some((record.u8(72) & 64) >> 6)
             } else {
                 None
             },
-            nic_c: if ua8[75] & 8 != 0 {
-                Some((ua8[72] & 128) >> 7)
+            nic_c: if record.u8(75) & 8 != 0 {
+                Some((record.u8(72) & 128) >> 7)
             } else {
                 None
             },
             hex,
-            flight: if ua8[73] & 8 != 0 {
+            flight: if record.u8(73) & 8 != 0 {
                 Some(callsign.trim().to_owned())
             } else {
                 None
             },
-            rssi: 10.0 * (ua8[86] as f64 * ua8[86] as f64 / 65025.0 + 1.125e-5).ln() / 10f64.ln(),
-            db_flags: ua8[87],
+            rssi: 10.0 * (signal * signal / 65025.0 + 1.125e-5).ln() / 10f64.ln(),
+            db_flags,
             aircraft_type,
             registration,
-            receiver_count: ua8[104],
-            nic_baro: if ua8[75] & 16 != 0 {
-                Some(ua8[73] & 1)
+            receiver_count: record.u8(104),
+            nic_baro: if record.u8(75) & 16 != 0 {
+                Some(record.u8(73) & 1)
             } else {
                 None
             },
-            alert1: if ua8[77] & 8 != 0 {
-                Some(ua8[73] & 2)
+            alert1: if record.u8(77) & 8 != 0 {
+                Some(record.u8(73) & 2)
             } else {
                 None
             },
-            spi: if ua8[76] & 16 != 0 {
-                Some(ua8[73] & 4)
+            spi: if record.u8(76) & 16 != 0 {
+                Some(record.u8(73) & 4)
             } else {
                 None
             },
@@ -372,6 +492,7 @@ impl ADSBExData {
 #[derive(Debug)]
 pub struct BinCraftData {
     pub time: f64,
+    pub version: u32,
     pub ac_count: u32,
     pub global_index: u32,
     pub limits: BoundingLimits,
@@ -379,32 +500,300 @@ pub struct BinCraftData {
 }
 
 impl BinCraftData {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let vals: &[u32] = as_other_array(bytes, 5);
+    /// Decodes a tile which may be zstd compressed
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if !bytes.starts_with(&ZSTD_MAGIC) {
+            return Self::from_bytes(bytes);
+        }
+
+        let mut decompressed = Vec::new();
+        zstd::stream::read::Decoder::new(bytes)
+            .and_then(|decoder| {
+                decoder
+                    .take(MAX_DECOMPRESSED_SIZE)
+                    .read_to_end(&mut decompressed)
+            })
+            .map_err(DecodeError::Decompress)?;
+
+        Self::from_bytes(&decompressed)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(DecodeError::ShortHeader {
+                len: bytes.len(),
+                needed: HEADER_SIZE,
+            });
+        }
+
+        let header = ByteView::new(bytes);
+
+        let time = header.u32(0) as f64 / 1000.0 + header.u32(1) as f64 * 4294967.296;
+        let stride = header.u32(2) as usize;
+        let ac_count = header.u32(3);
+        let global_index = header.u32(4);
 
-        let time = (vals[0] / 1000) as f64 + (vals[1] as f64 * 4294967.296);
-        let stride = vals[2] as usize;
-        let ac_count = vals[3];
-        let global_index = vals[4];
+        if !LAYOUTS.iter().any(|x| x.stride == stride) {
+            return Err(DecodeError::UnknownStride(stride));
+        }
+        // The header takes up the first record
+        if bytes.len() < stride {
+            return Err(DecodeError::ShortHeader {
+                len: bytes.len(),
+                needed: stride,
+            });
+        }
 
-        let limits = BoundingLimits::from_bytes(&bytes[20..]);
+        let version = header.u32(HEADER_VERSION_INDEX);
+        let layout = LAYOUTS
+            .iter()
+            .find(|x| x.stride == stride && x.versions.contains(&version))
+            .ok_or(DecodeError::UnknownVersion { stride, version })?;
+
+        let limits = BoundingLimits::from_header(&header);
 
         // Aircraft list
         let mut aircraft = Vec::new();
         let mut offset = stride;
 
         while offset < bytes.len() {
-            aircraft.push(ADSBExData::from_bytes(&bytes[offset..], stride, time));
+            let record = match bytes.get(offset..offset + stride) {
+                Some(r) => r,
+                None => {
+                    return Err(DecodeError::TruncatedRecord {
+                        offset,
+                        stride,
+                        len: bytes.len(),
+                    })
+                }
+            };
+
+            aircraft.push(ADSBExData::from_record(
+                &ByteView::new(record),
+                layout,
+                time,
+            ));
 
             offset += stride;
         }
 
-        Self {
+        Ok(Self {
             time,
+            version,
             ac_count,
             global_index,
             limits,
             aircraft,
-        }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name: expr) => {
+            include_bytes!(concat!("../../tests/fixtures/bincraft/", $name))
+        };
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn decodes_header() {
+        let data = BinCraftData::from_bytes(fixture!("empty.binCraft")).unwrap();
+
+        assert_close(data.time, 1634567890.123);
+        assert_eq!(data.version, 0);
+        assert_eq!(data.ac_count, 0);
+        assert_eq!(data.global_index, 5420);
+        assert_eq!(data.limits.south, 39);
+        assert_eq!(data.limits.west, -75);
+        assert_eq!(data.limits.north, 45);
+        assert_eq!(data.limits.east, -69);
+        assert!(data.aircraft.is_empty());
+    }
+
+    #[test]
+    fn decodes_single_aircraft() {
+        let data = BinCraftData::from_bytes(fixture!("single.binCraft")).unwrap();
+        assert_eq!(data.aircraft.len(), 1);
+
+        let aircraft = &data.aircraft[0];
+        assert_eq!(aircraft.hex, "A1B2C3");
+        assert_eq!(aircraft.flight.as_deref(), Some("JBU123"));
+        assert_eq!(aircraft.squawk.as_deref(), Some("1234"));
+        assert_eq!(aircraft.aircraft_type, "A320");
+        assert_eq!(aircraft.registration, "N123JB");
+        assert_close(aircraft.lat.unwrap(), 42.3656);
+        assert_close(aircraft.lon.unwrap(), -71.0096);
+        assert_close(aircraft.alt_baro.unwrap(), 3000.0);
+        assert_close(aircraft.alt_geom.unwrap(), 3100.0);
+        assert_close(aircraft.gs.unwrap(), 180.5);
+        assert_close(aircraft.track.unwrap(), 270.0);
+        assert_eq!(aircraft.wd, Some(310));
+        assert_eq!(aircraft.ws, Some(25));
+        assert_eq!(aircraft.oat, Some(-5));
+        assert_eq!(aircraft.tat, Some(2));
+        assert_eq!(aircraft.category, "A3");
+        assert_eq!(aircraft.airground, 0);
+        assert_eq!(aircraft.receiver_count, 7);
+        assert_eq!(aircraft.db_flags, 2);
+        assert_close((aircraft.rssi * 1e4).round() / 1e4, -2.1101);
+        assert_eq!(aircraft.last_pos, Some(1));
+        assert_eq!(aircraft.last_seen, 1);
+        assert!(matches!(aircraft.track_type, TrackType::AdsbIcao));
+        assert!(aircraft.baro_rate.is_none());
+        assert!(aircraft.mach.is_none());
+        assert_close(aircraft.time, data.time);
+    }
+
+    #[test]
+    fn decodes_multiple_aircraft() {
+        let data = BinCraftData::from_bytes(fixture!("multiple.binCraft")).unwrap();
+        assert_eq!(data.ac_count, 3);
+        assert_eq!(data.aircraft.len(), 3);
+
+        let ground = &data.aircraft[1];
+        assert_eq!(ground.hex, "ACBDEF");
+        assert_eq!(ground.airground, 1);
+        assert!(ground.wd.is_none());
+
+        let no_position = &data.aircraft[2];
+        assert_eq!(no_position.hex, "~ABCDE");
+        assert!(no_position.lat.is_none());
+        assert!(no_position.lon.is_none());
+        assert!(no_position.flight.is_none());
+        assert_eq!(no_position.squawk.as_deref(), Some("7000"));
+        assert_close(no_position.alt_baro.unwrap(), 12000.0);
+    }
+
+    #[test]
+    fn decodes_unaligned_buffer() {
+        let bytes = fixture!("multiple.binCraft");
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(bytes);
+
+        let data = BinCraftData::from_bytes(&shifted[1..]).unwrap();
+        assert_eq!(data.aircraft.len(), 3);
+        assert_eq!(data.aircraft[0].hex, "A1B2C3");
+        assert_close(data.aircraft[0].lat.unwrap(), 42.3656);
+    }
+
+    #[test]
+    fn rejects_short_header() {
+        assert!(matches!(
+            BinCraftData::from_bytes(fixture!("short_header.binCraft")),
+            Err(DecodeError::ShortHeader {
+                len: 16,
+                needed: 28
+            })
+        ));
+        assert!(matches!(
+            BinCraftData::from_bytes(&[]),
+            Err(DecodeError::ShortHeader { len: 0, .. })
+        ));
+        // Header must fill a whole record
+        assert!(matches!(
+            BinCraftData::from_bytes(&fixture!("empty.binCraft")[..100]),
+            Err(DecodeError::ShortHeader {
+                len: 100,
+                needed: 112
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_stride() {
+        assert!(matches!(
+            BinCraftData::from_bytes(fixture!("unknown_stride.binCraft")),
+            Err(DecodeError::UnknownStride(96))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_record() {
+        assert!(matches!(
+            BinCraftData::from_bytes(fixture!("truncated.binCraft")),
+            Err(DecodeError::TruncatedRecord {
+                offset: 224,
+                stride: 112,
+                len: 326
+            })
+        ));
+    }
+
+    fn assert_jbu(data: &BinCraftData) {
+        assert_eq!(data.aircraft.len(), 1);
+
+        let aircraft = &data.aircraft[0];
+        assert_eq!(aircraft.hex, "A1B2C3");
+        assert_eq!(aircraft.flight.as_deref(), Some("JBU123"));
+        assert_eq!(aircraft.registration, "N123JB");
+        assert_close(aircraft.lat.unwrap(), 42.3656);
+        assert_close(aircraft.lon.unwrap(), -71.0096);
+        assert_close(aircraft.alt_baro.unwrap(), 3000.0);
+        assert_close(aircraft.gs.unwrap(), 180.5);
+        assert_eq!(aircraft.wd, Some(310));
+        assert_eq!(aircraft.receiver_count, 7);
+        assert_eq!(aircraft.last_pos, Some(1));
+        assert_eq!(aircraft.last_seen, 1);
+    }
+
+    #[test]
+    fn decodes_wide_db_flags_layout() {
+        let data = BinCraftData::from_bytes(fixture!("wide_db_flags.binCraft")).unwrap();
+        assert_eq!(data.version, 20210101);
+        assert_jbu(&data);
+        assert_eq!(data.aircraft[0].db_flags, 0x0102);
+        assert_close((data.aircraft[0].rssi * 1e4).round() / 1e4, -2.1101);
+    }
+
+    #[test]
+    fn decodes_wide_seen_layout() {
+        let data = BinCraftData::from_bytes(fixture!("wide_seen.binCraft")).unwrap();
+        assert_eq!(data.version, 20220916);
+        assert_jbu(&data);
+        assert_eq!(data.aircraft[0].db_flags, 0x0102);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        assert!(matches!(
+            BinCraftData::from_bytes(fixture!("unknown_version.binCraft")),
+            Err(DecodeError::UnknownVersion {
+                stride: 112,
+                version: 20220916
+            })
+        ));
+    }
+
+    #[test]
+    fn decodes_zstd() {
+        let data = BinCraftData::decode(fixture!("single.binCraft.zst")).unwrap();
+        assert_jbu(&data);
+
+        // Uncompressed data passes straight through
+        let data = BinCraftData::decode(fixture!("single.binCraft")).unwrap();
+        assert_jbu(&data);
+    }
+
+    #[test]
+    fn rejects_corrupt_zstd() {
+        let mut bytes = fixture!("single.binCraft.zst").to_vec();
+        bytes.truncate(20);
+
+        assert!(matches!(
+            BinCraftData::decode(&bytes),
+            Err(DecodeError::Decompress(_))
+        ));
     }
 }
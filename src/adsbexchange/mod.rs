@@ -1,8 +1,10 @@
 mod bincraft;
+mod trace;
 mod util;
 pub use bincraft::*;
 use log::warn;
 use rand::{distributions::Alphanumeric, Rng};
+pub use trace::*;
 
 use crate::error::Error;
 use crate::util::{AircraftData, AircraftMap, AircraftProvider, Bounds};
@@ -11,10 +13,12 @@ use cookie::Cookie;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::iter;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
-use std::time::SystemTime;
+use std::thread;
+use std::time::{Instant, SystemTime};
 
 const GLOBE_INDEX_GRID: f32 = 3.0;
+// Seconds before aircraft from a tile that couldn't be refreshed are dropped
+const TILE_EXPIRY: u64 = 60;
 const ENDPOINT: &str = "https://globe.adsbexchange.com";
 
 fn get_global_index(lat: f32, lon: f32) -> u16 {
@@ -34,11 +38,59 @@ struct GlobeRates {
     simload: usize,
 }
 
+fn create_session() -> Session {
+    let mut session = attohttpc::Session::new();
+    session.header("Referer", "https://globe.adsbexchange.com");
+    session.header("Host", "globe.adsbexchange.com");
+    session
+}
+
+fn authorize_session(session: &mut Session) -> Result<(), Error> {
+    let response = session.head(ENDPOINT).send()?;
+    // We expect to get a Set-Cookie from this which will allow us to make more requests
+    let mut cookies: Vec<String> = response
+        .headers()
+        .get_all("Set-Cookie")
+        .into_iter()
+        .map(|x| {
+            let cookie = Cookie::parse(x.to_str().unwrap()).unwrap();
+            return format!("{}={}", cookie.name(), cookie.value());
+        })
+        .collect();
+
+    // generate random adsbx_sid
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 2 * 86400 * 1000;
+
+    let random_chars = iter::repeat(())
+        .map(|_| rand::thread_rng().sample(Alphanumeric))
+        .take(11)
+        .map(|x| x as char)
+        .collect::<String>();
+
+    let sid = format!("adsbx_sid={}_{}", time, random_chars);
+    cookies.push(sid);
+
+    session.header("Cookie", cookies.join("; "));
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Tile {
+    // When the tile was last fetched successfully
+    fetched_at: Option<Instant>,
+    aircraft: AircraftMap,
+}
+
 pub struct AdsbExchange {
-    global_indexes: HashSet<u16>,
+    tiles: HashMap<u16, Tile>,
     session: Session,
-    last_fetched_index: AtomicUsize,
-    requests_per_interval: usize,
+    // How many tiles the server allows to be fetched at once
+    max_concurrent_requests: usize,
 }
 
 impl AdsbExchange {
@@ -85,16 +137,13 @@ impl AdsbExchange {
             lon += GLOBE_INDEX_GRID;
         }
 
-        // Setup HTTP session
-        let mut session = attohttpc::Session::new();
-        session.header("Referer", "https://globe.adsbexchange.com");
-        session.header("Host", "globe.adsbexchange.com");
-
         let mut adsb = Self {
-            global_indexes,
-            session,
-            requests_per_interval: 4,
-            last_fetched_index: AtomicUsize::new(0),
+            tiles: global_indexes
+                .into_iter()
+                .map(|index| (index, Tile::default()))
+                .collect(),
+            session: create_session(),
+            max_concurrent_requests: 4,
         };
 
         adsb.fetch_cookie().ok();
@@ -103,35 +152,7 @@ impl AdsbExchange {
     }
 
     fn fetch_cookie(&mut self) -> Result<(), Error> {
-        let response = self.session.head(ENDPOINT).send()?;
-        // We expect to get a Set-Cookie from this which will allow us to make more requests
-        let mut cookies: Vec<String> = response
-            .headers()
-            .get_all("Set-Cookie")
-            .into_iter()
-            .map(|x| {
-                let cookie = Cookie::parse(x.to_str().unwrap()).unwrap();
-                return format!("{}={}", cookie.name(), cookie.value());
-            })
-            .collect();
-
-        // generate random adsbx_sid
-        let time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-            + 2 * 86400 * 1000;
-
-        let random_chars = iter::repeat(())
-            .map(|_| rand::thread_rng().sample(Alphanumeric))
-            .take(11)
-            .map(|x| x as char)
-            .collect::<String>();
-
-        let sid = format!("adsbx_sid={}_{}", time, random_chars);
-        cookies.push(sid);
-
-        self.session.header("Cookie", cookies.join("; "));
+        authorize_session(&mut self.session)?;
 
         // Validate SID by sending request to globeRates.json
         if let Ok(globe_rates) = self
@@ -140,7 +161,7 @@ impl AdsbExchange {
             .send()
             .and_then(|x| x.json::<GlobeRates>())
         {
-            self.requests_per_interval = globe_rates.simload;
+            self.max_concurrent_requests = globe_rates.simload;
         }
 
         Ok(())
@@ -155,46 +176,86 @@ impl AdsbExchange {
     }
 }
 
+fn fetch_tile(mut request: PreparedRequest<Empty>) -> Result<BinCraftData, Error> {
+    let bytes = request.send()?.error_for_status()?.bytes()?;
+    Ok(BinCraftData::decode(&bytes)?)
+}
+
 impl AircraftProvider for AdsbExchange {
     fn get_aircraft(&mut self) -> Result<AircraftMap, Error> {
-        let mut return_data = HashMap::new();
-
-        // No tiles to fetch
-        if self.global_indexes.len() == 0 {
-            return Ok(return_data);
+        // Every tile is refreshed each poll, with no more requests in flight than the server allows.
+        // The tiles that have gone the longest without an update go first.
+        let mut indexes: Vec<(u16, Option<Instant>)> = self
+            .tiles
+            .iter()
+            .map(|(index, tile)| (*index, tile.fetched_at))
+            .collect();
+        indexes.sort_by_key(|(_, fetched_at)| *fetched_at);
+
+        let mut results = Vec::new();
+
+        for batch in indexes.chunks(self.max_concurrent_requests.max(1)) {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|(index, _)| {
+                    let request = self.get_request(index);
+                    (*index, thread::spawn(move || fetch_tile(request)))
+                })
+                .collect();
+
+            results.extend(
+                handles
+                    .into_iter()
+                    .map(|(index, handle)| (index, handle.join())),
+            );
         }
 
-        let last_fetched = self.last_fetched_index.load(SeqCst);
-        let mut fetched = 0;
-
-        for index in self
-            .global_indexes
-            .iter()
-            .skip(last_fetched)
-            .take(self.requests_per_interval)
-        {
-            let response = match self.get_request(index).send()?.error_for_status() {
-                Ok(r) => r,
-                Err(e) => {
-                    warn!("Error fetching index {} from ADSBExchange: {}", index, e);
-                    break;
+        for (index, result) in results {
+            let parsed_data = match result {
+                Ok(Ok(d)) => d,
+                Ok(Err(e)) => {
+                    warn!("Error fetching index {} from ADSBExchange: {:?}", index, e);
+                    continue;
                 }
+                Err(_) => continue,
             };
 
-            let bytes = response.bytes()?;
+            if let Some(tile) = self.tiles.get_mut(&index) {
+                tile.fetched_at = Some(Instant::now());
+                tile.aircraft = parsed_data
+                    .aircraft
+                    .into_iter()
+                    .map(|aircraft| (aircraft.hex.clone(), aircraft.into()))
+                    .collect();
+            }
+        }
 
-            let parsed_data = BinCraftData::from_bytes(&bytes);
+        // Merge the latest data of every tile
+        let mut return_data: AircraftMap = HashMap::new();
 
-            for aircraft in parsed_data.aircraft {
-                let ident = aircraft.hex.clone();
-                return_data.insert(ident, aircraft.into());
+        for tile in self.tiles.values_mut() {
+            let is_expired = tile
+                .fetched_at
+                .map(|x| x.elapsed().as_secs() >= TILE_EXPIRY)
+                .unwrap_or(true);
+
+            if is_expired {
+                tile.aircraft.clear();
+                continue;
             }
 
-            fetched += 1;
-        }
+            for (hex, aircraft) in tile.aircraft.iter() {
+                // Aircraft near tile edges can show up in more than one tile
+                let is_newer = return_data
+                    .get(hex)
+                    .map(|x| aircraft.timestamp > x.timestamp)
+                    .unwrap_or(true);
 
-        self.last_fetched_index
-            .store((last_fetched + fetched) % self.global_indexes.len(), SeqCst);
+                if is_newer {
+                    return_data.insert(hex.clone(), aircraft.clone());
+                }
+            }
+        }
 
         Ok(return_data)
     }
@@ -220,6 +281,16 @@ impl Into<AircraftData> for ADSBExData {
             hex: self.hex,
             origin: String::new(),
             destination: String::new(),
+            registration: self.registration,
+            airline: String::new(),
+            flight: String::new(),
+            vertical_rate: self.baro_rate.map(|x| x as i32).unwrap_or_default(),
+            radar: String::new(),
+            fr24_id: String::new(),
+            wind_direction: self.wd.map(|x| x as u32),
+            wind_speed: self.ws.map(|x| x as u32),
+            temperature: self.oat.map(|x| x as i32),
+            geometric_altitude: self.alt_geom.map(|x| x as i32),
         }
     }
 }
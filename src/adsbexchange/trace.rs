@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+use super::{authorize_session, create_session, ENDPOINT};
+use crate::error::Error;
+use crate::request::Request;
+use crate::util::TrackPoint;
+
+// Pause after each trace request, so a busy area isn't requested all at once
+const TRACE_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceBackfill {
+    Off,
+    // Roughly the last hour of the flight
+    Recent,
+    // Everything ADSBExchange has for the day
+    Full,
+}
+
+impl Default for TraceBackfill {
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+#[derive(Debug)]
+struct TraceRequest {
+    id: String,
+    hex: String,
+}
+
+#[derive(Debug)]
+pub struct TraceResult {
+    pub id: String,
+    pub points: Vec<TrackPoint>,
+}
+
+// https://github.com/wiedehopf/readsb/blob/dev/README-json.md#trace-jsons
+fn get_point_from_json(base_timestamp: f64, data: &Value) -> Option<TrackPoint> {
+    let data = data.as_array()?;
+
+    let (altitude, is_on_ground) = match data.get(3)? {
+        Value::String(s) if s == "ground" => (0, true),
+        value => (value.as_f64()? as i32, false),
+    };
+
+    Some(TrackPoint {
+        timestamp: (base_timestamp + data.get(0)?.as_f64()?) as u64,
+        latitude: data.get(1)?.as_f64()? as f32,
+        longitude: data.get(2)?.as_f64()? as f32,
+        altitude,
+        ground_speed: data.get(4)?.as_f64().unwrap_or_default() as u32,
+        heading: data.get(5)?.as_f64().unwrap_or_default() as u32,
+        is_on_ground,
+    })
+}
+
+fn get_trace_from_json(data: &Value) -> Option<Vec<TrackPoint>> {
+    let base_timestamp = data.get("timestamp")?.as_f64()?;
+
+    Some(
+        data.get("trace")?
+            .as_array()?
+            .iter()
+            .filter_map(|x| get_point_from_json(base_timestamp, x))
+            .collect(),
+    )
+}
+
+pub struct AdsbTrace {
+    traces: Request<Result<TraceResult, Error>, TraceRequest>,
+    pub running: bool,
+}
+
+impl AdsbTrace {
+    pub fn new() -> Self {
+        Self {
+            traces: Request::new(2),
+            running: false,
+        }
+    }
+
+    pub fn run(&mut self, backfill: TraceBackfill) {
+        let kind = match backfill {
+            TraceBackfill::Off => return,
+            TraceBackfill::Recent => "recent",
+            TraceBackfill::Full => "full",
+        };
+
+        let mut session = create_session();
+        authorize_session(&mut session).ok();
+
+        self.running = true;
+
+        self.traces.run(move |job| {
+            let hex = job.hex.to_lowercase();
+            let url = format!(
+                "{}/data/traces/{}/trace_{}_{}.json",
+                ENDPOINT,
+                &hex[hex.len() - 2..],
+                kind,
+                hex
+            );
+
+            let response = session.get(url).send();
+            thread::sleep(TRACE_REQUEST_INTERVAL);
+
+            let data: Value = response?.error_for_status()?.json()?;
+
+            match get_trace_from_json(&data) {
+                Some(points) => Ok(TraceResult { id: job.id, points }),
+                None => Err(Error::NotFound),
+            }
+        });
+    }
+
+    /// Lower priorities are requested first, only one request is queued per id
+    pub fn request_trace(&self, id: &str, hex: &str, priority: u32) {
+        // Non-ICAO addresses (prefixed with ~) have no trace files
+        if hex.len() != 6 || !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+            return;
+        }
+
+        self.traces.give_job_with_priority(
+            id,
+            priority,
+            TraceRequest {
+                id: id.to_string(),
+                hex: hex.to_string(),
+            },
+        );
+    }
+
+    /// Drops queued requests for aircraft that aren't tracked anymore
+    pub fn cancel_requests<F>(&self, is_tracked: F)
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.traces.cancel_jobs(is_tracked);
+    }
+
+    pub fn get_next_trace(&self) -> Option<Result<TraceResult, Error>> {
+        self.traces.get_next()
+    }
+}
+
+impl Drop for AdsbTrace {
+    fn drop(&mut self) {
+        self.traces.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_trace() {
+        let data: Value = serde_json::from_str(include_str!(
+            "../../tests/fixtures/trace/trace_recent_a1b2c3.json"
+        ))
+        .unwrap();
+        let points = get_trace_from_json(&data).unwrap();
+
+        // The point without a position is skipped
+        assert_eq!(points.len(), 3);
+
+        // Offsets are added to the trace's timestamp
+        assert_eq!(points[0].timestamp, 1697630000);
+        assert_eq!(points[1].timestamp, 1697630035);
+        assert_eq!(points[2].timestamp, 1697630063);
+
+        assert!(points[0].is_on_ground);
+        assert_eq!(points[0].altitude, 0);
+        assert_eq!(points[0].ground_speed, 12);
+        assert_eq!(points[0].heading, 45);
+
+        assert!(!points[1].is_on_ground);
+        assert_eq!(points[1].altitude, 1200);
+        assert_eq!(points[1].latitude, 42.3701);
+        assert_eq!(points[1].longitude, -70.9981);
+
+        // Missing track
+        assert_eq!(points[2].heading, 0);
+    }
+
+    #[test]
+    fn requires_timestamp() {
+        let data: Value = serde_json::from_str(r#"{"trace": [[0.0, 42.0, -71.0, 1000]]}"#).unwrap();
+        assert!(get_trace_from_json(&data).is_none());
+    }
+}
@@ -1,11 +1,57 @@
 use super::bincraft::{NavModes, TrackType};
 
-pub fn as_other_array<T>(bytes: &[u8], len: usize) -> &[T] {
-    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, len) }
+use std::convert::TryInto;
+use std::ops::Range;
+
+/// Little endian view over a binCraft buffer, indexed like the typed arrays used by tar1090.
+/// Reads are done byte-wise so the buffer does not need to be aligned.
+pub struct ByteView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn array<const N: usize>(&self, index: usize) -> [u8; N] {
+        self.bytes[index * N..(index + 1) * N].try_into().unwrap()
+    }
+
+    pub fn u8(&self, index: usize) -> u8 {
+        self.bytes[index]
+    }
+
+    pub fn u16(&self, index: usize) -> u16 {
+        u16::from_le_bytes(self.array(index))
+    }
+
+    pub fn s16(&self, index: usize) -> i16 {
+        i16::from_le_bytes(self.array(index))
+    }
+
+    pub fn u32(&self, index: usize) -> u32 {
+        u32::from_le_bytes(self.array(index))
+    }
+
+    pub fn s32(&self, index: usize) -> i32 {
+        i32::from_le_bytes(self.array(index))
+    }
+
+    pub fn bytes(&self, range: Range<usize>) -> &'a [u8] {
+        &self.bytes[range]
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> ByteView<'a> {
+        ByteView::new(self.bytes(range))
+    }
 }
 
 pub fn convert_char_array_to_string(bytes: &[u8]) -> String {
-    return String::from_utf8_lossy(bytes).to_string();
+    // Unused characters are null padded
+    return String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .to_string();
 }
 
 pub fn get_track_type_from_num(num: u8) -> TrackType {
@@ -1,19 +1,180 @@
+use crate::error::Error;
 use crate::util::{convert_miles_to_lat, convert_miles_to_lon, Bounds, LatLon};
 use csv;
+use log::info;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 
+// Full airports.csv from OurAirports, with names, types and elevations
+const OURAIRPORTS_URL: &str = "https://davidmegginson.github.io/ourairports-data/airports.csv";
+
+// Airports are indexed by position in squares this many degrees wide
+const GRID_SIZE: f32 = 1.0;
+const NM_PER_DEGREE: f32 = 60.0;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AirportKind {
+    LargeAirport,
+    MediumAirport,
+    SmallAirport,
+    Heliport,
+    SeaplaneBase,
+    Balloonport,
+    Closed,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Default for AirportKind {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
+impl AirportKind {
+    // Used to list busier airports first
+    fn get_rank(&self) -> u8 {
+        match self {
+            Self::LargeAirport => 0,
+            Self::MediumAirport => 1,
+            Self::SmallAirport => 2,
+            Self::Unknown => 3,
+            Self::SeaplaneBase | Self::Heliport | Self::Balloonport => 4,
+            Self::Closed => 5,
+        }
+    }
+}
+
+/// A row of OurAirports' airports.csv. Only the position and codes are required, so stripped down files still load.
 #[derive(Deserialize, Debug)]
 pub struct AirportData {
     pub iata_code: String,
     pub latitude_deg: f32,
     pub longitude_deg: f32,
     pub gps_code: String,
+    #[serde(default)]
+    pub ident: String,
+    #[serde(default)]
+    pub icao_code: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub elevation_ft: Option<i32>,
+    #[serde(default, rename = "type")]
+    pub kind: AirportKind,
+    #[serde(default)]
+    pub iso_country: String,
+    #[serde(default)]
+    pub municipality: String,
+}
+
+impl AirportData {
+    // Smaller fields often only have a local identifier
+    fn get_code(&self) -> &String {
+        [&self.icao_code, &self.gps_code, &self.ident]
+            .iter()
+            .find(|x| **x != "")
+            .unwrap_or(&&self.gps_code)
+    }
+
+    fn get_position(&self) -> LatLon {
+        LatLon {
+            lat: self.latitude_deg,
+            lon: self.longitude_deg,
+        }
+    }
+}
+
+fn get_cell(position: &LatLon) -> (i32, i32) {
+    (
+        (position.lat / GRID_SIZE).floor() as i32,
+        (position.lon / GRID_SIZE).floor() as i32,
+    )
+}
+
+/// Grid squares that could hold a point within radius nm of the position
+fn get_cells_near(position: &LatLon, radius: f32) -> HashSet<(i32, i32)> {
+    let lon_cell_count = (360.0 / GRID_SIZE) as i32;
+    let radius_deg = radius / NM_PER_DEGREE;
+
+    // Degrees of longitude get shorter towards the poles
+    let max_lat = (position.lat.abs() + radius_deg).min(90.0);
+    let lon_radius_deg = radius_deg / max_lat.to_radians().cos();
+
+    let (lat, lon) = get_cell(position);
+    let lat_cells = (radius_deg / GRID_SIZE).ceil() as i32;
+    let lon_cells = if lon_radius_deg.is_finite() {
+        ((lon_radius_deg / GRID_SIZE).ceil() as i32).min(lon_cell_count / 2)
+    } else {
+        lon_cell_count / 2
+    };
+
+    let mut cells = HashSet::new();
+    for lat_offset in -lat_cells..=lat_cells {
+        for lon_offset in -lon_cells..=lon_cells {
+            // Wrap around the antimeridian
+            let lon = (lon + lon_offset + lon_cell_count / 2).rem_euclid(lon_cell_count)
+                - lon_cell_count / 2;
+            cells.insert((lat + lat_offset, lon));
+        }
+    }
+
+    cells
+}
+
+/// Whether the file has the columns of the full OurAirports schema, rather than only positions and codes
+fn has_full_schema(filename: &str) -> bool {
+    let mut reader = match csv::Reader::from_path(filename) {
+        Ok(r) => r,
+        Err(_) => return false,
+    };
+
+    match reader.headers() {
+        Ok(headers) => ["name", "type", "elevation_ft"]
+            .iter()
+            .all(|x| headers.iter().any(|h| h == *x)),
+        Err(_) => false,
+    }
+}
+
+/// Replaces a missing or stripped down airports file with the full one from OurAirports.
+/// Returns whether it was downloaded, the existing file is kept if anything fails.
+pub fn download_airport_data(filename: &str) -> Result<bool, Error> {
+    if has_full_schema(filename) {
+        return Ok(false);
+    }
+
+    info!("Downloading airport data from {}", OURAIRPORTS_URL);
+
+    let bytes = attohttpc::get(OURAIRPORTS_URL)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    // Written next to the file first, so a failed download can't leave it half written
+    let partial = Path::new(filename).with_extension("csv.part");
+    fs::write(&partial, bytes)?;
+
+    if !has_full_schema(&partial.to_string_lossy()) {
+        fs::remove_file(&partial).ok();
+        return Err(Error::InvalidData(
+            "downloaded airport data is missing columns",
+        ));
+    }
+
+    fs::rename(&partial, filename)?;
+
+    Ok(true)
 }
 
 pub struct Airports {
     db: HashMap<String, AirportData>,
     iata_icao_map: HashMap<String, String>,
+    // Codes by grid square, so nearby airports are found without going through all of them
+    grid: HashMap<(i32, i32), Vec<String>>,
 }
 
 impl Airports {
@@ -21,26 +182,101 @@ impl Airports {
         let mut reader = csv::Reader::from_path(filename)?;
         let mut db = HashMap::new();
         let mut iata_icao_map = HashMap::new();
+        let mut grid: HashMap<(i32, i32), Vec<String>> = HashMap::new();
 
         for record in reader.deserialize() {
             let record: AirportData = record?;
+            let code = record.get_code().clone();
+            if code == "" {
+                continue;
+            }
+
             // No IATA code
             if record.iata_code != "" {
-                iata_icao_map.insert(record.iata_code.clone(), record.gps_code.clone());
+                iata_icao_map.insert(record.iata_code.clone(), code.clone());
             }
 
-            db.insert(record.gps_code.clone(), record);
+            // Codes listed twice keep the last row
+            if let Some(previous) = db.insert(code.clone(), record) {
+                if let Some(codes) = grid.get_mut(&get_cell(&previous.get_position())) {
+                    codes.retain(|x| *x != code);
+                }
+            }
+            grid.entry(get_cell(&db[&code].get_position()))
+                .or_default()
+                .push(code);
         }
 
-        Ok(Self { db, iata_icao_map })
+        Ok(Self {
+            db,
+            iata_icao_map,
+            grid,
+        })
+    }
+
+    pub fn get_airport(&self, icao: &str) -> Option<&AirportData> {
+        self.db.get(icao)
     }
 
     pub fn get_lat_lon(&self, icao: &String) -> Option<LatLon> {
-        let data = self.db.get(icao)?;
-        Some(LatLon {
-            lat: data.latitude_deg,
-            lon: data.longitude_deg,
-        })
+        Some(self.db.get(icao)?.get_position())
+    }
+
+    pub fn get_elevation(&self, icao: &str) -> Option<i32> {
+        self.db.get(icao)?.elevation_ft
+    }
+
+    /// Airports within radius nm of the position, closest first
+    pub fn get_airports_near(&self, position: &LatLon, radius: f32) -> Vec<(&String, f32)> {
+        let mut airports: Vec<(&String, f32)> = get_cells_near(position, radius)
+            .iter()
+            .filter_map(|x| self.grid.get(x))
+            .flatten()
+            .filter_map(|icao| self.db.get_key_value(icao))
+            .map(|(icao, data)| (icao, position.distance_to(&data.get_position())))
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+
+        airports.sort_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(b.0))
+        });
+
+        airports
+    }
+
+    /// Closest open airport within radius nm of the position
+    pub fn get_nearest_airport(&self, position: &LatLon, radius: f32) -> Option<(&String, f32)> {
+        self.get_airports_near(position, radius)
+            .into_iter()
+            .find(|(icao, _)| self.db[*icao].kind != AirportKind::Closed)
+    }
+
+    /// Airports whose name or city contains every word of the query, busiest first
+    pub fn find_by_name(&self, query: &str) -> Vec<(&String, &AirportData)> {
+        let words: Vec<String> = query.split_whitespace().map(|x| x.to_uppercase()).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut airports: Vec<(&String, &AirportData)> = self
+            .db
+            .iter()
+            .filter(|(_, data)| {
+                let text = format!("{} {}", data.name, data.municipality).to_uppercase();
+                words.iter().all(|x| text.contains(x.as_str()))
+            })
+            .collect();
+
+        airports.sort_by(|a, b| {
+            a.1.kind
+                .get_rank()
+                .cmp(&b.1.kind.get_rank())
+                .then_with(|| a.0.cmp(b.0))
+        });
+
+        airports
     }
 
     pub fn get_bounds_from_radius(&self, icao: &String, radius: f32) -> Option<Bounds> {
@@ -62,3 +298,148 @@ impl Airports {
         self.iata_icao_map.get(iata)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_fixture(name: &str) -> Airports {
+        Airports::new(&format!(
+            "{}/tests/fixtures/airports/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn detects_full_schema() {
+        let path = |name: &str| {
+            format!(
+                "{}/tests/fixtures/airports/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+
+        assert!(has_full_schema(&path("full.csv")));
+        assert!(!has_full_schema(&path("minimal.csv")));
+        assert!(!has_full_schema(&path("missing.csv")));
+    }
+
+    #[test]
+    fn loads_stripped_down_file() {
+        let airports = load_fixture("minimal.csv");
+
+        let boston = airports.get_airport("KBOS").unwrap();
+        assert_eq!(boston.kind, AirportKind::Unknown);
+        assert_eq!(boston.name, "");
+        assert_eq!(boston.elevation_ft, None);
+        assert_eq!(airports.get_icao_from_iata("BOS").unwrap(), "KBOS");
+        assert_eq!(airports.get_icao_from_iata(""), None);
+        assert!(airports.get_airport("2B2").is_some());
+
+        let position = LatLon {
+            lat: 42.36,
+            lon: -71.0,
+        };
+        assert_eq!(
+            airports.get_nearest_airport(&position, 2.0).unwrap().0,
+            "KBOS"
+        );
+    }
+
+    #[test]
+    fn loads_full_file() {
+        let airports = load_fixture("full.csv");
+
+        let boston = airports.get_airport("KBOS").unwrap();
+        assert_eq!(boston.kind, AirportKind::LargeAirport);
+        assert_eq!(boston.municipality, "Boston");
+        assert_eq!(airports.get_elevation("KBOS"), Some(20));
+        assert_eq!(airports.get_icao_from_iata("BED").unwrap(), "KBED");
+        // Rows without any code are skipped
+        assert_eq!(airports.db.len(), 8);
+    }
+
+    #[test]
+    fn prefers_icao_then_gps_code_then_ident() {
+        let airports = load_fixture("full.csv");
+
+        // Listed under their ICAO code even though the GPS code differs
+        assert!(airports.get_airport("KXYZ").is_some());
+        assert!(airports.get_airport("XYZ1").is_none());
+        // No ICAO code
+        assert!(airports.get_airport("MA88").is_some());
+        assert!(airports.get_airport("US-0001").is_none());
+        // Only a local identifier
+        assert!(airports.get_airport("MA99").is_some());
+    }
+
+    #[test]
+    fn ranks_name_matches_by_size() {
+        let airports = load_fixture("full.csv");
+
+        let codes: Vec<&String> = airports
+            .find_by_name("boston")
+            .into_iter()
+            .map(|(icao, _)| icao)
+            .collect();
+        assert_eq!(codes, vec!["KBOS", "MA99", "KCLS"]);
+
+        // Every word has to match, in the name or the city
+        let codes: Vec<&String> = airports
+            .find_by_name("bedford hanscom")
+            .into_iter()
+            .map(|(icao, _)| icao)
+            .collect();
+        assert_eq!(codes, vec!["KBED"]);
+        assert!(airports.find_by_name("  ").is_empty());
+    }
+
+    #[test]
+    fn skips_closed_airports_when_finding_nearest() {
+        let airports = load_fixture("full.csv");
+        // Right on top of the closed field, a mile from KBOS
+        let position = LatLon {
+            lat: 42.36,
+            lon: -71.02,
+        };
+
+        let near = airports.get_airports_near(&position, 2.0);
+        assert_eq!(near[0].0, "KCLS");
+        assert_eq!(
+            airports.get_nearest_airport(&position, 2.0).unwrap().0,
+            "KBOS"
+        );
+        assert!(airports
+            .get_nearest_airport(&LatLon { lat: 0.0, lon: 0.0 }, 2.0)
+            .is_none());
+    }
+
+    #[test]
+    fn finds_airports_across_grid_edges() {
+        let airports = load_fixture("full.csv");
+
+        // Across the antimeridian
+        let position = LatLon {
+            lat: -16.5,
+            lon: -179.95,
+        };
+        let (icao, distance) = airports.get_nearest_airport(&position, 10.0).unwrap();
+        assert_eq!(icao, "NFFX");
+        assert!(distance < 10.0);
+
+        // Across a whole degree of latitude
+        let position = LatLon {
+            lat: 41.99,
+            lon: -71.0,
+        };
+        let codes: Vec<&String> = airports
+            .get_airports_near(&position, 30.0)
+            .into_iter()
+            .map(|(icao, _)| icao)
+            .collect();
+        assert!(codes.iter().any(|x| *x == "KBOS"));
+    }
+}
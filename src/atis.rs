@@ -0,0 +1,290 @@
+use crate::metar::{CloudCover, Metar};
+
+const PHONETIC_ALPHABET: [&str; 26] = [
+    "ALPHA", "BRAVO", "CHARLIE", "DELTA", "ECHO", "FOXTROT", "GOLF", "HOTEL", "INDIA", "JULIET",
+    "KILO", "LIMA", "MIKE", "NOVEMBER", "OSCAR", "PAPA", "QUEBEC", "ROMEO", "SIERRA", "TANGO",
+    "UNIFORM", "VICTOR", "WHISKEY", "XRAY", "YANKEE", "ZULU",
+];
+
+/// Broadcast for the configured airport, composed from its latest METAR
+pub struct Atis {
+    pub airport: String,
+    letter: usize,
+    metar: Option<Metar>,
+    runways: Vec<String>,
+    // Seen in use by traffic, for when no runways are configured
+    arrival_runways: Vec<String>,
+    departure_runways: Vec<String>,
+    text: String,
+}
+
+fn describe_visibility(miles: f32) -> String {
+    if miles >= 10.0 {
+        "10 OR MORE".to_string()
+    } else if miles >= 1.0 {
+        format!("{:.0}", miles.floor())
+    } else {
+        format!("{:.2}", miles)
+    }
+}
+
+fn describe_cloud_cover(cover: CloudCover) -> &'static str {
+    match cover {
+        CloudCover::Few => "FEW CLOUDS",
+        CloudCover::Scattered => "SCATTERED",
+        CloudCover::Broken => "BROKEN",
+        CloudCover::Overcast => "OVERCAST",
+        CloudCover::VerticalVisibility => "INDEFINITE CEILING",
+    }
+}
+
+impl Atis {
+    pub fn new(airport: &str, runways: Vec<String>, text: String) -> Self {
+        Self {
+            airport: airport.to_uppercase(),
+            letter: 0,
+            metar: None,
+            runways,
+            arrival_runways: Vec::new(),
+            departure_runways: Vec::new(),
+            text,
+        }
+    }
+
+    pub fn get_callsign(&self) -> String {
+        format!("{}_ATIS", self.airport)
+    }
+
+    pub fn get_letter(&self) -> &'static str {
+        PHONETIC_ALPHABET[self.letter]
+    }
+
+    /// Advances the letter when the weather changes, returns whether it did
+    pub fn update_metar(&mut self, metar: &Metar) -> bool {
+        let is_changed = match &self.metar {
+            Some(current) => current.raw != metar.raw,
+            None => false,
+        };
+
+        if is_changed {
+            self.letter = (self.letter + 1) % PHONETIC_ALPHABET.len();
+        }
+
+        self.metar = Some(metar.clone());
+
+        is_changed
+    }
+
+    /// Returns whether the runways in use changed. The letter only advances if they were already known.
+    pub fn update_runways(&mut self, arrivals: Vec<String>, departures: Vec<String>) -> bool {
+        if arrivals == self.arrival_runways && departures == self.departure_runways {
+            return false;
+        }
+
+        let was_known = !self.arrival_runways.is_empty() || !self.departure_runways.is_empty();
+        if was_known && self.runways.is_empty() {
+            self.letter = (self.letter + 1) % PHONETIC_ALPHABET.len();
+        }
+
+        self.arrival_runways = arrivals;
+        self.departure_runways = departures;
+
+        true
+    }
+
+    pub fn get_active_runways(&self) -> Option<String> {
+        let mut parts = Vec::new();
+
+        if !self.arrival_runways.is_empty() {
+            parts.push(format!(
+                "LANDING RUNWAY {}",
+                self.arrival_runways.join(", ")
+            ));
+        }
+        if !self.departure_runways.is_empty() {
+            parts.push(format!(
+                "DEPARTING RUNWAY {}",
+                self.departure_runways.join(", ")
+            ));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(", "))
+        }
+    }
+
+    /// One sentence per line
+    pub fn get_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} INFORMATION {}",
+            self.airport,
+            self.get_letter()
+        )];
+
+        let metar = match &self.metar {
+            Some(m) => m,
+            None => {
+                lines.push("WEATHER NOT AVAILABLE".to_string());
+                return lines;
+            }
+        };
+
+        if let Some((_, hour, minute)) = metar.time {
+            lines.push(format!("{:02}{:02}Z OBSERVATION", hour, minute));
+        }
+
+        if let Some(wind) = &metar.wind {
+            let mut line = match wind.direction {
+                _ if wind.speed == 0 => "WIND CALM".to_string(),
+                Some(direction) => format!("WIND {:03} AT {}", direction, wind.speed),
+                None => format!("WIND VARIABLE AT {}", wind.speed),
+            };
+            if let Some(gust) = wind.gust {
+                line += &format!(" GUSTS {}", gust);
+            }
+            if let Some((from, to)) = wind.variation {
+                line += &format!(", VARIABLE BETWEEN {:03} AND {:03}", from, to);
+            }
+            lines.push(line);
+        }
+
+        if let Some(visibility) = metar.visibility {
+            lines.push(format!("VISIBILITY {}", describe_visibility(visibility)));
+        }
+
+        for rvr in metar.rvr.iter() {
+            lines.push(format!("RUNWAY {} RVR {}", rvr.runway, rvr.visual_range));
+        }
+
+        if !metar.weather.is_empty() {
+            lines.push(metar.weather.join(" "));
+        }
+
+        if metar.clouds.is_empty() {
+            lines.push("SKY CLEAR".to_string());
+        } else {
+            let clouds: Vec<String> = metar
+                .clouds
+                .iter()
+                .map(|x| match x.base {
+                    Some(base) => format!("{} {}", describe_cloud_cover(x.cover), base),
+                    None => describe_cloud_cover(x.cover).to_string(),
+                })
+                .collect();
+            lines.push(clouds.join(", "));
+        }
+
+        if let (Some(temperature), Some(dewpoint)) = (metar.temperature, metar.dewpoint) {
+            lines.push(format!(
+                "TEMPERATURE {}, DEWPOINT {}",
+                temperature, dewpoint
+            ));
+        }
+
+        if let (Some(inhg), Some(hpa)) = (metar.altimeter_inhg, metar.altimeter_hpa) {
+            lines.push(format!("ALTIMETER {:.0}, QNH {:.0}", inhg * 100.0, hpa));
+        }
+
+        if !self.runways.is_empty() {
+            lines.push(format!("RUNWAYS IN USE {}", self.runways.join(", ")));
+        } else if let Some(active) = self.get_active_runways() {
+            lines.push(active);
+        }
+
+        if self.text != "" {
+            lines.push(self.text.to_uppercase());
+        }
+
+        lines.push(format!(
+            "ADVISE ON INITIAL CONTACT YOU HAVE INFORMATION {}",
+            self.get_letter()
+        ));
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advances_letter_on_new_weather() {
+        let mut atis = Atis::new("kbos", Vec::new(), String::new());
+        let metar = Metar::parse("KBOS 181454Z 27015KT 10SM FEW050 18/M02 A2992");
+
+        // The first report only sets the weather
+        assert!(!atis.update_metar(&metar));
+        assert_eq!(atis.get_letter(), "ALPHA");
+
+        // The same report again
+        assert!(!atis.update_metar(&metar));
+        assert_eq!(atis.get_letter(), "ALPHA");
+
+        assert!(atis.update_metar(&Metar::parse(
+            "KBOS 181554Z 27012KT 10SM FEW050 18/M02 A2993"
+        )));
+        assert_eq!(atis.get_letter(), "BRAVO");
+    }
+
+    #[test]
+    fn wraps_letter_after_zulu() {
+        let mut atis = Atis::new("KBOS", Vec::new(), String::new());
+        atis.update_metar(&Metar::parse("KBOS 180054Z 27015KT 10SM CLR 18/M02 A2992"));
+
+        for hour in 1..=26 {
+            let raw = format!("KBOS 18{:02}54Z 27015KT 10SM CLR 18/M02 A2992", hour % 24);
+            atis.update_metar(&Metar::parse(&raw));
+        }
+        assert_eq!(atis.get_letter(), "ALPHA");
+    }
+
+    #[test]
+    fn describes_calm_wind() {
+        let mut atis = Atis::new(
+            "KBOS",
+            vec!["4R".to_string()],
+            "Read back all hold short instructions".to_string(),
+        );
+        atis.update_metar(&Metar::parse("KBOS 181454Z 00000KT 10SM CLR 18/M02 A2992"));
+
+        let lines = atis.get_lines();
+        assert_eq!(lines[0], "KBOS INFORMATION ALPHA");
+        assert_eq!(lines[1], "1454Z OBSERVATION");
+        assert_eq!(lines[2], "WIND CALM");
+        assert_eq!(lines[3], "VISIBILITY 10 OR MORE");
+        assert_eq!(lines[4], "SKY CLEAR");
+        assert!(lines.contains(&"RUNWAYS IN USE 4R".to_string()));
+        assert!(lines.contains(&"READ BACK ALL HOLD SHORT INSTRUCTIONS".to_string()));
+        assert_eq!(
+            lines.last().unwrap(),
+            "ADVISE ON INITIAL CONTACT YOU HAVE INFORMATION ALPHA"
+        );
+    }
+
+    #[test]
+    fn describes_variable_wind_and_low_visibility() {
+        let mut atis = Atis::new("KJFK", Vec::new(), String::new());
+        atis.update_metar(&Metar::parse(
+            "KJFK 181451Z VRB03KT 3/4SM BR VV004 M01/M01 A3001",
+        ));
+
+        let lines = atis.get_lines();
+        assert_eq!(lines[2], "WIND VARIABLE AT 3");
+        assert_eq!(lines[3], "VISIBILITY 0.75");
+        assert!(lines.contains(&"INDEFINITE CEILING 400".to_string()));
+        assert!(lines.contains(&"TEMPERATURE -1, DEWPOINT -1".to_string()));
+    }
+
+    #[test]
+    fn describes_missing_weather() {
+        let atis = Atis::new("KBOS", Vec::new(), String::new());
+
+        assert_eq!(
+            atis.get_lines(),
+            vec!["KBOS INFORMATION ALPHA", "WEATHER NOT AVAILABLE"]
+        );
+    }
+}
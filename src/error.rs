@@ -1,9 +1,13 @@
+use crate::adsbexchange::DecodeError;
+
 #[derive(Debug)]
 pub enum Error {
     RequestFailed(attohttpc::Error),
     CsvParseError(csv::Error),
     JSONParseError(serde_json::Error),
     IOError(std::io::Error),
+    DecodeError(DecodeError),
+    InvalidData(&'static str),
     NotFound,
 }
 
@@ -30,3 +34,9 @@ impl From<std::io::Error> for Error {
         Self::IOError(e)
     }
 }
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Self::DecodeError(e)
+    }
+}
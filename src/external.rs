@@ -0,0 +1,263 @@
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::{
+    atomic::{AtomicBool, Ordering::SeqCst},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::Error;
+use crate::util::{AircraftData, AircraftMap, AircraftProvider};
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+// How often the supervisor checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExternalSource {
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    Socket {
+        path: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ExternalConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: ExternalSource,
+    // Seconds without a record before the source is restarted. Off by default, since a
+    // healthy feed is quiet when nothing is in range.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+enum Connection {
+    Process(Child),
+    #[cfg(unix)]
+    Socket(std::os::unix::net::UnixStream),
+}
+
+impl Connection {
+    fn open(source: &ExternalSource) -> Result<(Self, Box<dyn Read + Send>), Error> {
+        match source {
+            ExternalSource::Command { program, args } => {
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+
+                let stdout = child.stdout.take().ok_or(Error::NotFound)?;
+
+                Ok((Self::Process(child), Box::new(stdout)))
+            }
+            #[cfg(unix)]
+            ExternalSource::Socket { path } => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                let reader = stream.try_clone()?;
+
+                Ok((Self::Socket(stream), Box::new(reader)))
+            }
+            #[cfg(not(unix))]
+            ExternalSource::Socket { .. } => Err(Error::IOError(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Unix sockets are not supported on this platform",
+            ))),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Self::Process(child) => {
+                child.kill().ok();
+                child.wait().ok();
+            }
+            #[cfg(unix)]
+            Self::Socket(stream) => {
+                stream.shutdown(std::net::Shutdown::Both).ok();
+            }
+        }
+    }
+}
+
+fn parse_record(line: &str) -> Option<AircraftData> {
+    let mut data: AircraftData = match serde_json::from_str(line) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("Could not parse external aircraft record! Reason: {}", e);
+            return None;
+        }
+    };
+
+    // Aircraft are keyed by their hex
+    if data.hex == "" {
+        return None;
+    }
+
+    // Feeds without their own clock are stamped on arrival
+    if data.timestamp == 0 {
+        data.timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+    }
+
+    Some(data)
+}
+
+fn read_lines(reader: Box<dyn Read + Send>, tx: Sender<AircraftData>) {
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+
+        if line.trim() == "" {
+            continue;
+        }
+
+        if let Some(data) = parse_record(&line) {
+            if tx.send(data).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn supervise(config: ExternalConfig, tx: Sender<AircraftData>, stopped: Arc<AtomicBool>) {
+    let timeout = config.timeout.map(Duration::from_secs);
+
+    while !stopped.load(SeqCst) {
+        let (mut connection, reader) = match Connection::open(&config.source) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(
+                    "Could not start external provider {}! Reason: {:?}",
+                    config.name, e
+                );
+                thread::sleep(RESTART_DELAY);
+                continue;
+            }
+        };
+
+        info!("Started external provider {}", config.name);
+
+        // Lines are read on their own thread so a silent source can be timed out
+        let (line_tx, line_rx) = unbounded();
+        let reader_thread = thread::spawn(move || read_lines(reader, line_tx));
+        let mut last_record = Instant::now();
+
+        loop {
+            match line_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(data) => {
+                    last_record = Instant::now();
+                    tx.send(data).ok();
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(timeout) = timeout.filter(|x| last_record.elapsed() >= *x) {
+                        warn!(
+                            "External provider {} sent no data for {} seconds, restarting.",
+                            config.name,
+                            timeout.as_secs()
+                        );
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("External provider {} exited, restarting.", config.name);
+                    break;
+                }
+            }
+
+            if stopped.load(SeqCst) {
+                break;
+            }
+        }
+
+        connection.close();
+        reader_thread.join().ok();
+
+        if !stopped.load(SeqCst) {
+            thread::sleep(RESTART_DELAY);
+        }
+    }
+}
+
+pub struct ExternalProvider {
+    name: String,
+    rx: Receiver<AircraftData>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ExternalProvider {
+    pub fn new(config: &ExternalConfig) -> Self {
+        let (tx, rx) = unbounded();
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let config_clone = config.clone();
+        let stopped_clone = stopped.clone();
+        thread::spawn(move || supervise(config_clone, tx, stopped_clone));
+
+        Self {
+            name: config.name.clone(),
+            rx,
+            stopped,
+        }
+    }
+}
+
+impl AircraftProvider for ExternalProvider {
+    fn get_aircraft(&mut self) -> Result<AircraftMap, Error> {
+        let mut return_data = AircraftMap::new();
+
+        // Only the latest record for each aircraft is kept
+        for data in self.rx.try_iter() {
+            return_data.insert(data.hex.clone(), data);
+        }
+
+        Ok(return_data)
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for ExternalProvider {
+    fn drop(&mut self) {
+        self.stopped.store(true, SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_hex() {
+        assert!(parse_record(r#"{"callsign": "JBU123", "latitude": 42.3}"#).is_none());
+        assert!(parse_record(r#"{"hex": "", "callsign": "JBU123"}"#).is_none());
+        assert!(parse_record("not json").is_none());
+    }
+
+    #[test]
+    fn fills_missing_timestamp() {
+        let data = parse_record(r#"{"hex": "a1b2c3", "altitude": 3000}"#).unwrap();
+        assert_eq!(data.hex, "a1b2c3");
+        assert_eq!(data.altitude, 3000);
+        assert!(data.timestamp > 0);
+
+        let data = parse_record(r#"{"hex": "a1b2c3", "timestamp": 1700000000}"#).unwrap();
+        assert_eq!(data.timestamp, 1700000000);
+    }
+}
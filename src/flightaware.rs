@@ -1,53 +1,19 @@
 use attohttpc;
-use chrono::{serde::ts_seconds, DateTime, Utc};
-use regex;
-use serde::{de::DeserializeOwned, Deserialize};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
 use crate::error::Error;
-use crate::request::Request;
+use crate::flightplan::{
+    Aircraft, Airport, FlightPlan, FlightPlanProvider, PartialFlightPlan, Times,
+};
+use crate::util::is_airline_callsign;
 
 const ENDPOINT: &str = "https://flightaware.com/live/flight/";
 
-#[derive(Debug)]
-pub struct FlightPlan {
-    pub origin: Airport,
-    pub destination: Airport,
-    pub equipment: Aircraft,
-    pub fp: PartialFlightPlan,
-    pub arrival_time: Option<Times>,
-    pub departure_time: Option<Times>,
-}
-
-#[derive(Debug, Deserialize, Default)]
-pub struct Aircraft {
-    #[serde(rename = "type")]
-    #[serde(default)]
-    pub ac_type: String,
-}
-
-#[derive(Debug, Deserialize, Default)]
-pub struct PartialFlightPlan {
-    #[serde(default)]
-    pub speed: u64,
-    #[serde(default)]
-    pub altitude: u64,
-    #[serde(default)]
-    pub route: String,
-}
-
-#[derive(Debug, Deserialize, Default)]
-pub struct Airport {
-    #[serde(default)]
-    pub icao: String,
-    pub gate: Option<String>,
-    pub terminal: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct Times {
-    #[serde(with = "ts_seconds")]
-    pub scheduled: DateTime<Utc>,
+lazy_static! {
+    static ref BOOTSTRAP_REGEX: Regex = Regex::new(r"var trackpollBootstrap = (\{.+\});").unwrap();
 }
 
 fn deserialize_or_none<T>(data: &Value, key: &str) -> Option<T>
@@ -89,81 +55,54 @@ fn get_flightplan_from_json(data: &Value) -> Option<FlightPlan> {
         equipment: aircraft,
         arrival_time,
         departure_time,
+        // FlightAware doesn't publish the filed alternate or fuel
+        alternate: None,
+        fuel_minutes: None,
     });
 }
 
-#[derive(Debug)]
-struct FlightPlanRequest {
-    id: String,
-    callsign: String,
-}
-
-#[derive(Debug)]
-pub struct FlightPlanResult {
-    pub id: String,
-    pub callsign: String,
-    pub fp: FlightPlan,
-}
-
-pub struct FlightAware {
-    flightplans: Request<Result<FlightPlanResult, Error>, FlightPlanRequest>,
-    pub running: bool,
-}
+pub struct FlightAware {}
 
 impl FlightAware {
     pub fn new() -> Self {
-        Self {
-            flightplans: Request::new(5),
-            running: false,
-        }
+        Self {}
     }
+}
+
+impl FlightPlanProvider for FlightAware {
+    fn get_flightplan(&self, callsign: &str) -> Result<FlightPlan, Error> {
+        if !self.can_provide(callsign) {
+            return Err(Error::NotFound);
+        }
+
+        // Get data from flightaware
+        let text = attohttpc::get(ENDPOINT.to_owned() + callsign)
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        // Parse json from html
+        let data = match BOOTSTRAP_REGEX.captures(&text).and_then(|x| x.get(1)) {
+            Some(m) => m.as_str(),
+            None => return Err(Error::NotFound),
+        };
 
-    pub fn run(&mut self) {
-        let exp = regex::Regex::new(r"var trackpollBootstrap = (\{.+\});").unwrap();
-        self.running = true;
-
-        self.flightplans.run(move |job| {
-            // Get data from flightaware
-            let text = attohttpc::get(ENDPOINT.to_owned() + &job.callsign)
-                .send()?
-                .error_for_status()?
-                .text()?;
-
-            let mut data: &str = "";
-            // Parse json from html
-            for cap in exp.captures(&text) {
-                data = cap.get(1).unwrap().as_str();
-                break;
-            }
-
-            // Deserialize into Value
-            let data: Value = serde_json::from_str(data)?;
-
-            match get_flightplan_from_json(&data) {
-                Some(fp) => Ok(FlightPlanResult {
-                    callsign: job.callsign,
-                    id: job.id,
-                    fp: fp,
-                }),
-                None => Err(Error::NotFound),
-            }
-        });
+        // Deserialize into Value
+        let data: Value = serde_json::from_str(data)?;
+
+        get_flightplan_from_json(&data).ok_or(Error::NotFound)
     }
 
-    pub fn request_flightplan(&self, id: &str, callsign: &str) {
-        self.flightplans.give_job(FlightPlanRequest {
-            id: id.to_string(),
-            callsign: callsign.to_string(),
-        });
+    fn get_name(&self) -> &str {
+        "FlightAware"
     }
 
-    pub fn get_next_flightplan(&self) -> Option<Result<FlightPlanResult, Error>> {
-        return self.flightplans.get_next();
+    // Only airliners are looked up to limit requests
+    fn can_provide(&self, callsign: &str) -> bool {
+        is_airline_callsign(callsign)
     }
-}
 
-impl Drop for FlightAware {
-    fn drop(&mut self) {
-        self.flightplans.stop()
+    fn should_cache(&self) -> bool {
+        true
     }
 }
@@ -0,0 +1,254 @@
+use chrono::{
+    serde::{ts_seconds, ts_seconds_option},
+    DateTime, Duration, Utc,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::airports::Airports;
+use crate::error::Error;
+use crate::request::Request;
+use crate::util::{heading_difference, AircraftData};
+
+// Extra distance allowed when flying between airports, as a fraction of the direct distance
+const ROUTE_DETOUR_FACTOR: f32 = 0.5;
+// Plus a fixed amount in nm for short flights, vectors and holds
+const ROUTE_DETOUR_SLACK: f32 = 100.0;
+// Aircraft closer than this to either airport (in nm) aren't checked for direction
+const DIRECTION_CHECK_DISTANCE: f32 = 50.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlightPlan {
+    pub origin: Airport,
+    pub destination: Airport,
+    pub equipment: Aircraft,
+    pub fp: PartialFlightPlan,
+    pub arrival_time: Option<Times>,
+    pub departure_time: Option<Times>,
+    #[serde(default)]
+    pub alternate: Option<String>,
+    // Fuel on board in minutes, only known from route files
+    #[serde(default)]
+    pub fuel_minutes: Option<u32>,
+}
+
+impl FlightPlan {
+    pub fn get_proposed_departure(&self) -> Option<DateTime<Utc>> {
+        Some(self.departure_time.as_ref()?.scheduled)
+    }
+
+    pub fn get_actual_departure(&self) -> Option<DateTime<Utc>> {
+        self.departure_time.as_ref()?.actual
+    }
+
+    /// Time between the scheduled departure and arrival
+    pub fn get_enroute_time(&self) -> Option<Duration> {
+        let enroute =
+            self.arrival_time.as_ref()?.scheduled - self.departure_time.as_ref()?.scheduled;
+
+        if enroute > Duration::zero() {
+            Some(enroute)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_fuel_time(&self) -> Option<Duration> {
+        Some(Duration::minutes(self.fuel_minutes? as i64))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Aircraft {
+    #[serde(rename = "type")]
+    #[serde(default)]
+    pub ac_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PartialFlightPlan {
+    #[serde(default)]
+    pub speed: u64,
+    #[serde(default)]
+    pub altitude: u64,
+    #[serde(default)]
+    pub route: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Airport {
+    #[serde(default)]
+    pub icao: String,
+    pub gate: Option<String>,
+    pub terminal: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Times {
+    #[serde(with = "ts_seconds")]
+    pub scheduled: DateTime<Utc>,
+    #[serde(default, with = "ts_seconds_option")]
+    pub estimated: Option<DateTime<Utc>>,
+    #[serde(default, with = "ts_seconds_option")]
+    pub actual: Option<DateTime<Utc>>,
+}
+
+/// Checks the plan against where the aircraft is and where it is heading.
+/// Airports missing from the database are given the benefit of the doubt.
+pub fn is_plausible(fp: &FlightPlan, ac_data: &AircraftData, airports: &Airports) -> bool {
+    let origin = match airports.get_lat_lon(&fp.origin.icao) {
+        Some(p) => p,
+        None => return true,
+    };
+    let destination = match airports.get_lat_lon(&fp.destination.icao) {
+        Some(p) => p,
+        None => return true,
+    };
+    let position = ac_data.get_position();
+
+    let to_origin = position.distance_to(&origin);
+    let to_destination = position.distance_to(&destination);
+    let direct = origin.distance_to(&destination);
+
+    // Too far off the route between the two airports
+    if to_origin + to_destination > direct * (1.0 + ROUTE_DETOUR_FACTOR) + ROUTE_DETOUR_SLACK {
+        return false;
+    }
+
+    if ac_data.is_on_ground
+        || to_origin < DIRECTION_CHECK_DISTANCE
+        || to_destination < DIRECTION_CHECK_DISTANCE
+    {
+        return true;
+    }
+
+    // Flying away from the destination, likely the return leg
+    heading_difference(ac_data.heading as f32, position.bearing_to(&destination)) < 90.0
+}
+
+pub trait FlightPlanProvider {
+    fn get_flightplan(&self, callsign: &str) -> Result<FlightPlan, Error>;
+    fn get_name(&self) -> &str;
+    /// Cheap check for whether asking about the callsign is worth it at all
+    fn can_provide(&self, _callsign: &str) -> bool {
+        true
+    }
+    /// Whether results are worth keeping in the on-disk cache
+    fn should_cache(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+struct FlightPlanRequest {
+    id: String,
+    callsign: String,
+}
+
+#[derive(Debug)]
+pub struct FlightPlanResult {
+    pub id: String,
+    pub callsign: String,
+    pub source: String,
+    pub should_cache: bool,
+    pub fp: FlightPlan,
+}
+
+/// Asks each provider in order until one has a flight plan
+pub struct FlightPlans {
+    // Errors are returned with the id of the aircraft
+    flightplans: Request<Result<FlightPlanResult, (String, Error)>, FlightPlanRequest>,
+    providers: Arc<Vec<Box<dyn FlightPlanProvider + Send + Sync>>>,
+    pub running: bool,
+}
+
+impl FlightPlans {
+    pub fn new() -> Self {
+        Self {
+            flightplans: Request::new(5),
+            providers: Arc::new(Vec::new()),
+            running: false,
+        }
+    }
+
+    pub fn run(&mut self, providers: Vec<Box<dyn FlightPlanProvider + Send + Sync>>) {
+        // Nothing to ask
+        if providers.len() == 0 {
+            return;
+        }
+
+        let providers = Arc::new(providers);
+        self.providers = providers.clone();
+        self.running = true;
+
+        self.flightplans.run(move |job| {
+            let mut error = Error::NotFound;
+
+            for provider in providers.iter() {
+                match provider.get_flightplan(&job.callsign) {
+                    Ok(fp) => {
+                        return Ok(FlightPlanResult {
+                            id: job.id,
+                            callsign: job.callsign,
+                            source: provider.get_name().to_string(),
+                            should_cache: provider.should_cache(),
+                            fp,
+                        })
+                    }
+                    Err(Error::NotFound) => {}
+                    Err(e) => {
+                        info!(
+                            "{} could not get a flight plan for {}: {:?}",
+                            provider.get_name(),
+                            job.callsign,
+                            e
+                        );
+                        error = e;
+                    }
+                }
+            }
+
+            Err((job.id, error))
+        });
+    }
+
+    /// Lower priorities are requested first, only one request is queued per callsign
+    pub fn request_flightplan(&self, id: &str, callsign: &str, priority: u32) {
+        self.flightplans.give_job_with_priority(
+            callsign,
+            priority,
+            FlightPlanRequest {
+                id: id.to_string(),
+                callsign: callsign.to_string(),
+            },
+        );
+    }
+
+    /// Whether any provider could have a flight plan for the callsign
+    pub fn can_provide(&self, callsign: &str) -> bool {
+        self.providers.iter().any(|x| x.can_provide(callsign))
+    }
+
+    pub fn is_queued(&self, callsign: &str) -> bool {
+        self.flightplans.has_job(callsign)
+    }
+
+    /// Drops queued requests for callsigns that aren't tracked anymore
+    pub fn cancel_requests<F>(&self, is_tracked: F)
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.flightplans.cancel_jobs(is_tracked);
+    }
+
+    pub fn get_next_flightplan(&self) -> Option<Result<FlightPlanResult, (String, Error)>> {
+        return self.flightplans.get_next();
+    }
+}
+
+impl Drop for FlightPlans {
+    fn drop(&mut self) {
+        self.flightplans.stop()
+    }
+}
@@ -1,49 +1,171 @@
 use crate::error::Error;
+use crate::request::Request;
 use crate::util::{AircraftData, AircraftMap, AircraftProvider, Bounds};
 
 use attohttpc;
+use log::warn;
 use serde::Deserialize;
 use serde_json::{self, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-const ENDPOINT: &str = "https://data-live.flightradar24.com/zones/fcgi/feed.js?faa=1&mlat=1&flarm=1&adsb=1&gnd=1&air=1&vehicles=1&estimated=1&gliders=1&stats=0&maxage=14400";
+const DETAILS_ENDPOINT: &str =
+    "https://data-live.flightradar24.com/clickhandler/?version=1.5&flight=";
+const ENDPOINT: &str = "https://data-live.flightradar24.com/zones/fcgi/feed.js?faa=1&mlat=1&flarm=1&adsb=1&gnd=1&air=1&vehicles=1&estimated=1&gliders=1&stats=1&maxage=14400";
+// Most aircraft the feed returns in a single response
+const FEED_LIMIT: usize = 1500;
+// How many times a saturated area is split into quadrants
+const MAX_SPLIT_DEPTH: u32 = 3;
+// Most feed requests made in one poll, including the first
+const MAX_REQUESTS_PER_POLL: usize = 13;
 
-pub struct FlightRadar {
-    base_url: String,
+struct Feed {
+    aircraft: AircraftMap,
+    is_saturated: bool,
 }
 
-impl FlightRadar {
-    pub fn new(radar_loc: &Bounds) -> Self {
-        Self {
-            base_url: format!(
-                "{}&bounds={:.2},{:.2},{:.2},{:.2}",
-                ENDPOINT, radar_loc.lat1, radar_loc.lat2, radar_loc.lon1, radar_loc.lon2
-            ),
+fn get_url(bounds: &Bounds) -> String {
+    format!(
+        "{}&bounds={:.2},{:.2},{:.2},{:.2}",
+        ENDPOINT, bounds.lat1, bounds.lat2, bounds.lon1, bounds.lon2
+    )
+}
+
+fn count_visible(stats: &Value) -> Option<u64> {
+    Some(
+        stats
+            .get("visible")?
+            .as_object()?
+            .values()
+            .filter_map(|x| x.as_u64())
+            .sum(),
+    )
+}
+
+fn fetch_feed(bounds: &Bounds) -> Result<Feed, Error> {
+    let response = attohttpc::get(get_url(bounds)).send()?.error_for_status()?;
+
+    parse_feed(&response.text()?)
+}
+
+fn parse_feed(text: &str) -> Result<Feed, Error> {
+    let data: Value = serde_json::from_str(text)?;
+    let data = data
+        .as_object()
+        .ok_or(Error::InvalidData("FlightRadar24 feed is not an object"))?;
+
+    let mut aircraft = HashMap::new();
+    let mut rows = 0;
+
+    // Iterate through aircraft
+    for (id, value) in data {
+        // Skip over stats data like numbers and objects
+        if !value.is_array() {
+            continue;
+        }
+
+        rows += 1;
+
+        match serde_json::from_value::<FRData>(value.clone()) {
+            Ok(data) => {
+                let mut data: AircraftData = data.into();
+                data.fr24_id = id.clone();
+                aircraft.insert(data.hex.clone(), data);
+            }
+            Err(e) => warn!("Skipping malformed FlightRadar24 aircraft {}: {}", id, e),
         }
     }
+
+    let visible = data.get("stats").and_then(count_visible).unwrap_or(0);
+
+    Ok(Feed {
+        aircraft,
+        is_saturated: rows >= FEED_LIMIT || visible > rows as u64,
+    })
 }
 
-impl AircraftProvider for FlightRadar {
-    fn get_aircraft(&mut self) -> Result<AircraftMap, Error> {
-        let response = attohttpc::get(&self.base_url).send()?.error_for_status()?;
+/// Fetches the area, splitting up tiles while the feed reports more aircraft than it returned
+fn fetch_tiled<F>(bounds: &Bounds, mut fetch: F) -> Result<AircraftMap, Error>
+where
+    F: FnMut(&Bounds) -> Result<Feed, Error>,
+{
+    let mut aircraft = HashMap::new();
+    let mut tiles = VecDeque::new();
+    tiles.push_back((bounds.clone(), 0));
 
-        let mut return_data = HashMap::new();
+    let mut requests = 0;
+    let mut succeeded = 0;
+    let mut last_error = None;
+    let mut is_limited = false;
 
-        let data: Value = serde_json::from_str(&response.text()?)?;
+    // Breadth first, so the request cap cuts off the smallest tiles
+    while let Some((tile, depth)) = tiles.pop_front() {
+        requests += 1;
 
-        // Iterate through aircraft
-        for (_, value) in data.as_object().unwrap() {
-            // Skip over stats data like numbers and objects
-            if !value.is_array() {
+        let feed = match fetch(&tile) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!(
+                    "Could not fetch FlightRadar24 tile {:.2},{:.2},{:.2},{:.2}: {:?}",
+                    tile.lat1, tile.lat2, tile.lon1, tile.lon2, e
+                );
+                last_error = Some(e);
                 continue;
             }
+        };
+        succeeded += 1;
 
-            let data: FRData = serde_json::from_value(value.clone()).unwrap();
+        for (id, data) in feed.aircraft {
+            // Aircraft on tile edges can be returned more than once
+            let is_newer = aircraft
+                .get(&id)
+                .map(|x: &AircraftData| data.timestamp > x.timestamp)
+                .unwrap_or(true);
 
-            return_data.insert(data.mode_s_code.clone(), data.into());
+            if is_newer {
+                aircraft.insert(id, data);
+            }
+        }
+
+        if !feed.is_saturated {
+            continue;
         }
 
-        Ok(return_data)
+        if depth >= MAX_SPLIT_DEPTH || requests + tiles.len() + 4 > MAX_REQUESTS_PER_POLL {
+            is_limited = true;
+            continue;
+        }
+
+        for quadrant in tile.split() {
+            tiles.push_back((quadrant, depth + 1));
+        }
+    }
+
+    if is_limited {
+        warn!("FlightRadar24 is still limiting aircraft after splitting the area, some aircraft may be missing.");
+    }
+
+    // Only a failure if nothing at all came back
+    match last_error {
+        Some(e) if succeeded == 0 => Err(e),
+        _ => Ok(aircraft),
+    }
+}
+
+pub struct FlightRadar {
+    bounds: Bounds,
+}
+
+impl FlightRadar {
+    pub fn new(radar_loc: &Bounds) -> Self {
+        Self {
+            bounds: radar_loc.clone(),
+        }
+    }
+}
+
+impl AircraftProvider for FlightRadar {
+    fn get_aircraft(&mut self) -> Result<AircraftMap, Error> {
+        fetch_tiled(&self.bounds, fetch_feed)
     }
 
     fn get_name(&self) -> &str {
@@ -90,6 +212,224 @@ impl Into<AircraftData> for FRData {
             hex: self.mode_s_code,
             origin: self.origin,
             destination: self.destination,
+            registration: self.registration,
+            airline: self.airline,
+            flight: self.flight,
+            vertical_rate: self.rate_of_climb,
+            radar: self.radar,
+            fr24_id: String::new(),
+            wind_direction: None,
+            wind_speed: None,
+            temperature: None,
+            geometric_altitude: None,
         }
     }
 }
+
+/// Information from FlightRadar24's per-flight details
+#[derive(Debug, Default, Clone)]
+pub struct FlightDetails {
+    pub origin: String,
+    pub destination: String,
+    pub registration: String,
+    pub airline: String,
+    pub flight: String,
+    pub model: String,
+}
+
+impl FlightDetails {
+    /// Fills in fields the feed left empty
+    pub fn apply(&self, data: &mut AircraftData) {
+        macro_rules! fill {
+            ($field: ident) => {
+                if data.$field == "" {
+                    data.$field = self.$field.clone();
+                }
+            };
+        }
+
+        fill!(origin);
+        fill!(destination);
+        fill!(registration);
+        fill!(airline);
+        fill!(flight);
+        fill!(model);
+    }
+}
+
+fn get_string(data: &Value, path: &[&str]) -> String {
+    path.iter()
+        .try_fold(data, |value, key| value.get(key))
+        .and_then(|x| x.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn get_airport_code(data: &Value, airport: &str) -> String {
+    let icao = get_string(data, &["airport", airport, "code", "icao"]);
+    if icao != "" {
+        return icao;
+    }
+    get_string(data, &["airport", airport, "code", "iata"])
+}
+
+fn get_details_from_json(data: &Value) -> FlightDetails {
+    FlightDetails {
+        origin: get_airport_code(data, "origin"),
+        destination: get_airport_code(data, "destination"),
+        registration: get_string(data, &["aircraft", "registration"]),
+        airline: get_string(data, &["airline", "code", "icao"]),
+        flight: get_string(data, &["identification", "number", "default"]),
+        model: get_string(data, &["aircraft", "model", "code"]),
+    }
+}
+
+#[derive(Debug)]
+pub struct FlightDetailsResult {
+    pub fr24_id: String,
+    pub details: FlightDetails,
+}
+
+pub struct FlightRadarDetails {
+    details: Request<Result<FlightDetailsResult, Error>, String>,
+    pub running: bool,
+}
+
+impl FlightRadarDetails {
+    pub fn new() -> Self {
+        Self {
+            details: Request::new(2),
+            running: false,
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.running = true;
+
+        self.details.run(move |fr24_id| {
+            let data: Value = attohttpc::get(DETAILS_ENDPOINT.to_owned() + &fr24_id)
+                .send()?
+                .error_for_status()?
+                .json()?;
+
+            Ok(FlightDetailsResult {
+                details: get_details_from_json(&data),
+                fr24_id,
+            })
+        });
+    }
+
+    pub fn request_details(&self, fr24_id: &str) {
+        self.details.give_job(fr24_id.to_string());
+    }
+
+    pub fn get_next_details(&self) -> Option<Result<FlightDetailsResult, Error>> {
+        self.details.get_next()
+    }
+}
+
+impl Drop for FlightRadarDetails {
+    fn drop(&mut self) {
+        self.details.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_row(hex: &str) -> String {
+        format!(
+            r#"["{}",42.3,-71.0,90,3000,250,"1234","T-KBOS1","A320","N123AB",1700000000,"BOS","JFK","B6123",0,0,"JBU123",0,"JBU"]"#,
+            hex
+        )
+    }
+
+    fn get_feed(hexes: &[&str], is_saturated: bool) -> Feed {
+        Feed {
+            aircraft: hexes
+                .iter()
+                .map(|x| {
+                    (
+                        x.to_string(),
+                        AircraftData {
+                            hex: x.to_string(),
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect(),
+            is_saturated,
+        }
+    }
+
+    fn get_bounds() -> Bounds {
+        Bounds {
+            lat1: 43.0,
+            lon1: -72.0,
+            lat2: 42.0,
+            lon2: -70.0,
+        }
+    }
+
+    #[test]
+    fn detects_saturated_feed() {
+        let text = format!(
+            r#"{{"full_count": 9000, "version": 4, "2d1": {}, "2d2": {}, "stats": {{"total": {{}}, "visible": {{"ads-b": 2, "mlat": 1}}}}}}"#,
+            get_row("A1"),
+            get_row("A2")
+        );
+        let feed = parse_feed(&text).unwrap();
+        assert_eq!(feed.aircraft.len(), 2);
+        assert_eq!(feed.aircraft["A1"].fr24_id, "2d1");
+        assert!(feed.is_saturated);
+
+        let text = format!(
+            r#"{{"2d1": {}, "stats": {{"visible": {{"ads-b": 1}}}}}}"#,
+            get_row("A1")
+        );
+        assert!(!parse_feed(&text).unwrap().is_saturated);
+    }
+
+    #[test]
+    fn keeps_tiles_that_succeeded() {
+        let root = get_bounds();
+        let failing = root.split()[1].clone();
+        let mut requests = 0;
+
+        let aircraft = fetch_tiled(&root, |tile| {
+            requests += 1;
+            if tile.lat1 == failing.lat1 && tile.lon1 == failing.lon1 && requests > 1 {
+                Err(Error::NotFound)
+            } else if requests == 1 {
+                Ok(get_feed(&["A1"], true))
+            } else {
+                Ok(get_feed(&[&format!("B{}", requests)], false))
+            }
+        })
+        .unwrap();
+
+        assert_eq!(requests, 5);
+        // The root and the three quadrants that answered
+        assert_eq!(aircraft.len(), 4);
+    }
+
+    #[test]
+    fn caps_requests_per_poll() {
+        let mut requests = 0;
+
+        fetch_tiled(&get_bounds(), |_| {
+            requests += 1;
+            Ok(get_feed(&[], true))
+        })
+        .unwrap();
+
+        assert!(requests <= MAX_REQUESTS_PER_POLL);
+        assert!(requests > 1);
+    }
+
+    #[test]
+    fn fails_when_nothing_answers() {
+        assert!(fetch_tiled(&get_bounds(), |_| Err(Error::NotFound)).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::time::Instant;
+
+use crate::error::Error;
+use crate::flightplan::FlightPlan;
+
+// How often new flight plans are written out
+const SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    fetched_at: DateTime<Utc>,
+    fp: FlightPlan,
+}
+
+/// Flight plans kept on disk between runs, keyed by callsign and UTC date
+pub struct FlightPlanCache {
+    filename: String,
+    ttl: Duration,
+    // Ignore cached entries, only writing new results
+    force_refresh: bool,
+    entries: HashMap<String, CacheEntry>,
+    // Whether there are entries not yet written to disk
+    is_dirty: bool,
+    last_saved: Instant,
+}
+
+impl FlightPlanCache {
+    pub fn load(filename: &str, ttl_hours: u64, force_refresh: bool) -> Self {
+        let entries = match File::open(filename) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
+                warn!(
+                    "Could not read flight plan cache {}! Reason: {}",
+                    filename, e
+                );
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        let mut cache = Self {
+            filename: filename.to_string(),
+            ttl: Duration::hours(ttl_hours as i64),
+            force_refresh,
+            entries,
+            is_dirty: false,
+            last_saved: Instant::now(),
+        };
+
+        cache.remove_expired();
+
+        cache
+    }
+
+    fn get_key(callsign: &str) -> String {
+        format!("{}:{}", callsign, Utc::now().format("%Y-%m-%d"))
+    }
+
+    fn is_expired(&self, entry: &CacheEntry) -> bool {
+        Utc::now() - entry.fetched_at > self.ttl
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Utc::now();
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, entry| now - entry.fetched_at <= ttl);
+    }
+
+    pub fn get(&self, callsign: &str) -> Option<FlightPlan> {
+        if self.force_refresh {
+            return None;
+        }
+
+        let entry = self.entries.get(&Self::get_key(callsign))?;
+
+        if self.is_expired(entry) {
+            return None;
+        }
+
+        Some(entry.fp.clone())
+    }
+
+    pub fn insert(&mut self, callsign: &str, fp: &FlightPlan) {
+        self.entries.insert(
+            Self::get_key(callsign),
+            CacheEntry {
+                fetched_at: Utc::now(),
+                fp: fp.clone(),
+            },
+        );
+
+        self.remove_expired();
+        self.is_dirty = true;
+    }
+
+    /// Writes new entries out if enough time has passed since the last write
+    /// Forgets the callsign's flight plan, such as when it turns out to be for another route
+    pub fn remove(&mut self, callsign: &str) {
+        if self.entries.remove(&Self::get_key(callsign)).is_some() {
+            self.is_dirty = true;
+        }
+    }
+
+    pub fn flush_if_due(&mut self) {
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if !self.is_dirty {
+            return;
+        }
+
+        if let Err(e) = self.save() {
+            warn!("Could not write flight plan cache! Reason: {:?}", e);
+        }
+
+        self.is_dirty = false;
+        self.last_saved = Instant::now();
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        serde_json::to_writer(File::create(&self.filename)?, &self.entries)?;
+        Ok(())
+    }
+}
+
+impl Drop for FlightPlanCache {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flightplan::{Aircraft, Airport, PartialFlightPlan};
+
+    fn get_filename(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("fpcache_{}_{}.json", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        path.to_string_lossy().to_string()
+    }
+
+    fn get_flightplan(origin: &str) -> FlightPlan {
+        FlightPlan {
+            origin: Airport {
+                icao: origin.to_string(),
+                ..Default::default()
+            },
+            destination: Airport::default(),
+            equipment: Aircraft::default(),
+            fp: PartialFlightPlan::default(),
+            arrival_time: None,
+            departure_time: None,
+            alternate: None,
+            fuel_minutes: None,
+        }
+    }
+
+    #[test]
+    fn writes_only_when_flushed() {
+        let filename = get_filename("flush");
+
+        let mut cache = FlightPlanCache::load(&filename, 12, false);
+        cache.insert("JBU123", &get_flightplan("KBOS"));
+        cache.flush_if_due();
+        assert!(File::open(&filename).is_err());
+
+        drop(cache);
+
+        let cache = FlightPlanCache::load(&filename, 12, false);
+        assert_eq!(cache.get("JBU123").unwrap().origin.icao, "KBOS");
+        assert!(cache.get("JBU456").is_none());
+
+        std::fs::remove_file(&filename).ok();
+    }
+
+    #[test]
+    fn drops_expired_entries() {
+        let filename = get_filename("expiry");
+
+        let mut entries = HashMap::new();
+        for (callsign, hours) in [("OLD1", 13), ("NEW1", 1)] {
+            entries.insert(
+                FlightPlanCache::get_key(callsign),
+                CacheEntry {
+                    fetched_at: Utc::now() - Duration::hours(hours),
+                    fp: get_flightplan("KBOS"),
+                },
+            );
+        }
+        serde_json::to_writer(File::create(&filename).unwrap(), &entries).unwrap();
+
+        let cache = FlightPlanCache::load(&filename, 12, false);
+        assert!(cache.get("OLD1").is_none());
+        assert!(cache.get("NEW1").is_some());
+        assert_eq!(cache.entries.len(), 1);
+
+        std::fs::remove_file(&filename).ok();
+    }
+
+    #[test]
+    fn refresh_ignores_cached_entries() {
+        let filename = get_filename("refresh");
+
+        let mut cache = FlightPlanCache::load(&filename, 12, false);
+        cache.insert("JBU123", &get_flightplan("KBOS"));
+        cache.flush();
+
+        let mut cache = FlightPlanCache::load(&filename, 12, true);
+        assert!(cache.get("JBU123").is_none());
+
+        // New results are still written for the next run
+        cache.insert("JBU123", &get_flightplan("KJFK"));
+        cache.flush();
+
+        let cache = FlightPlanCache::load(&filename, 12, false);
+        assert_eq!(cache.get("JBU123").unwrap().origin.icao, "KJFK");
+
+        std::fs::remove_file(&filename).ok();
+    }
+
+    #[test]
+    fn removes_entries() {
+        let filename = get_filename("remove");
+
+        let mut cache = FlightPlanCache::load(&filename, 12, false);
+        cache.insert("JBU123", &get_flightplan("KBOS"));
+        cache.flush();
+
+        cache.remove("JBU123");
+        assert!(cache.get("JBU123").is_none());
+        cache.flush();
+
+        let cache = FlightPlanCache::load(&filename, 12, false);
+        assert!(cache.get("JBU123").is_none());
+
+        std::fs::remove_file(&filename).ok();
+    }
+}
@@ -1,39 +1,73 @@
 mod adsbexchange;
 mod airports;
+mod atis;
 mod error;
+mod external;
 mod flightaware;
+mod flightplan;
 mod flightradar;
+mod fpcache;
 mod interpolate;
+mod metar;
+mod navdata;
 mod noaa;
+mod overrides;
 mod providers;
 mod request;
+mod routefile;
+mod runways;
+mod taf;
 mod tracker;
 mod updater;
 mod util;
+mod weather;
+mod weatherfile;
+mod winds;
 
-use airports::Airports;
-use flightaware::FlightPlan;
+use adsbexchange::TraceBackfill;
+use airports::{download_airport_data, AirportData, Airports};
+use atis::Atis;
+use chrono::{DateTime, Utc};
+use external::ExternalConfig;
+use flightaware::FlightAware;
+use flightplan::{FlightPlan, FlightPlanProvider};
+use fpcache::FlightPlanCache;
 use fsdparser::{ClientQueryPayload, PacketTypes, Parser};
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
+use navdata::NavData;
+use overrides::{FlightPlanOverride, FlightPlanOverrides};
 use retain_mut::RetainMut;
+use routefile::RouteFile;
+use runways::{RunwayUse, Runways};
 use serde::{Deserialize, Serialize};
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::fs::File;
 use std::io::{Read, Write};
 use std::net::TcpListener;
 use std::net::TcpStream;
+use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Instant;
 use std::{fmt::Display, time::Duration};
 use tracker::{TrackData, Tracker};
 use updater::Updater;
-use util::AircraftData;
+use util::{AircraftData, LatLon};
+use weather::{Weather, WeatherConfig};
+use winds::WindsAloft;
 
 const CONFIG_FILENAME: &str = "config.json";
 const AIRPORT_DATA_FILENAME: &str = "airports.csv";
+const RUNWAY_DATA_FILENAME: &str = "runways.csv";
+const FLIGHTPLAN_CACHE_FILENAME: &str = "flightplan_cache.json";
+// How often arrivals are sent their flight plan with a new ETA
+const ETA_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+// How close in nm an aircraft on the ground has to be to an airport to be at it
+const ON_GROUND_AIRPORT_RADIUS: f32 = 2.0;
+// Most airports listed for one .apt search
+const MAX_AIRPORT_RESULTS: usize = 5;
 
-fn build_aircraft_string(data: &mut TrackData, should_interpolate: bool) -> String {
+fn build_aircraft_string(data: &mut TrackData, should_interpolate: bool, altitude: i32) -> String {
     let ac_data = &data.ac_data;
     // Calculate PBH
     let h = ac_data.heading as f64 / 360.0 * 1024.0;
@@ -51,17 +85,69 @@ fn build_aircraft_string(data: &mut TrackData, should_interpolate: bool) -> Stri
         squawk = ac_data.squawk,
         lat = pos.lat,
         lon = pos.lon,
-        alt = ac_data.altitude,
+        alt = altitude,
         speed = ac_data.ground_speed,
         pbh = pbh
     )
 }
 
-fn get_remarks(ac_data: &AircraftData) -> String {
-    format!("Hex {}", ac_data.hex)
+fn get_remarks(data: &TrackData, airports: &Airports) -> String {
+    let ac_data = &data.ac_data;
+    let mut remarks = format!("Hex {}", ac_data.hex);
+
+    if ac_data.registration != "" {
+        remarks += &format!(", Reg {}", ac_data.registration);
+    }
+
+    if ac_data.is_on_ground {
+        if let Some((icao, _)) =
+            airports.get_nearest_airport(&ac_data.get_position(), ON_GROUND_AIRPORT_RADIUS)
+        {
+            remarks += &format!(", At {}", icao);
+        }
+    }
+
+    if let Some(distance) = data.get_distance_to_go(airports) {
+        remarks += &format!(", DTG {:.0}nm", distance);
+    }
+
+    if let Some(eta) = data.get_eta(airports) {
+        remarks += &format!(", ETA {}", eta.format("%H%MZ"));
+    }
+
+    remarks
+}
+
+/// Ground traffic is shown at the elevation of the airport it is at. The reported altitude is
+/// often missing or coarse on the ground, so it isn't corrected with the altimeter setting.
+fn get_ground_altitude(ac_data: &AircraftData, airports: &Airports) -> i32 {
+    airports
+        .get_nearest_airport(&ac_data.get_position(), ON_GROUND_AIRPORT_RADIUS)
+        .and_then(|(icao, _)| airports.get_elevation(icao))
+        .unwrap_or(ac_data.altitude)
 }
 
-fn build_flightplan_string(fp: &FlightPlan, ac_data: &AircraftData) -> String {
+// Times are sent as HHMM, or 0 when unknown
+fn format_flightplan_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|x| x.format("%H%M").to_string())
+        .unwrap_or("0".to_string())
+}
+
+fn split_hours_minutes(duration: Option<chrono::Duration>) -> (i64, i64) {
+    let minutes = duration.map(|x| x.num_minutes()).unwrap_or_default();
+    (minutes / 60, minutes % 60)
+}
+
+fn build_flightplan_string(
+    fp: &FlightPlan,
+    data: &TrackData,
+    airports: &Airports,
+    fp_override: Option<&FlightPlanOverride>,
+) -> String {
+    let ac_data = &data.ac_data;
+    let no_override = FlightPlanOverride::default();
+    let fp_override = fp_override.unwrap_or(&no_override);
+
     let fp_remarks = format!(
         "{}{}{}{}",
         fp.departure_time
@@ -90,30 +176,67 @@ fn build_flightplan_string(fp: &FlightPlan, ac_data: &AircraftData) -> String {
             .unwrap_or_default(),
     );
 
-    format!("$FP{callsign}::I:{equipment}:{speed}:{origin}:0:0:{altitude}:{destination}:0:0:0:0::/v/ {remarks}:{route}\r\n",
+    let (enroute_hours, enroute_minutes) = split_hours_minutes(fp.get_enroute_time());
+    let (fuel_hours, fuel_minutes) = split_hours_minutes(fp.get_fuel_time());
+
+    format!("$FP{callsign}::{flight_rules}:{equipment}:{speed}:{origin}:{departure}:{actual_departure}:{altitude}:{destination}:{enroute_hours}:{enroute_minutes}:{fuel_hours}:{fuel_minutes}:{alternate}:/v/ {remarks}:{route}\r\n",
         callsign = ac_data.callsign,
-        equipment = fp.equipment.ac_type,
-        speed = fp.fp.speed,
-        origin = fp.origin.icao,
-        altitude = fp.fp.altitude,
-        destination = fp.destination.icao,
-        route = fp.fp.route,
-        remarks = format!("{}, {}", get_remarks(ac_data), fp_remarks)
+        flight_rules = fp_override.flight_rules.as_deref().unwrap_or("I"),
+        departure = format_flightplan_time(fp.get_proposed_departure()),
+        actual_departure = format_flightplan_time(fp.get_actual_departure()),
+        enroute_hours = enroute_hours,
+        enroute_minutes = enroute_minutes,
+        fuel_hours = fuel_hours,
+        fuel_minutes = fuel_minutes,
+        alternate = fp_override.alternate.as_ref().or(fp.alternate.as_ref()).map(|x| x.as_str()).unwrap_or(""),
+        equipment = fp_override.equipment.as_ref().unwrap_or(&fp.equipment.ac_type),
+        speed = fp_override.speed.unwrap_or(fp.fp.speed),
+        origin = fp_override.origin.as_ref().unwrap_or(&fp.origin.icao),
+        altitude = fp_override.altitude.unwrap_or(fp.fp.altitude),
+        destination = fp_override.destination.as_ref().unwrap_or(&fp.destination.icao),
+        route = fp_override.route.as_ref().unwrap_or(&fp.fp.route),
+        remarks = format!("{}, {}", get_remarks(data, airports), fp_override.remarks.as_ref().unwrap_or(&fp_remarks))
     )
 }
 
-fn build_init_flightplan_string(ac_data: &AircraftData, airports: &Airports) -> String {
+fn build_init_flightplan_string(
+    data: &TrackData,
+    airports: &Airports,
+    fp_override: Option<&FlightPlanOverride>,
+) -> String {
+    let ac_data = &data.ac_data;
+    let no_override = FlightPlanOverride::default();
+    let fp_override = fp_override.unwrap_or(&no_override);
+
     format!(
-        "$FP{callsign}::{flight_rules}:{equipment}:0:{origin}:0:0:0:{destination}:0:0:0:0::/v/ {remarks}:\r\n",
-        flight_rules = if ac_data.is_airline() {"I"} else {"V"},
+        "$FP{callsign}::{flight_rules}:{equipment}:{speed}:{origin}:0:0:{altitude}:{destination}:0:0:0:0:{alternate}:/v/ {remarks}:{route}\r\n",
+        alternate = fp_override.alternate.as_deref().unwrap_or(""),
+        flight_rules = fp_override.flight_rules.as_deref().unwrap_or(if ac_data.is_airline() {"I"} else {"V"}),
         callsign = ac_data.callsign,
-        equipment = ac_data.model,
-        origin = airports.get_icao_from_iata(&ac_data.origin).unwrap_or(&ac_data.origin),
-        destination = airports.get_icao_from_iata(&ac_data.destination).unwrap_or(&ac_data.destination),
-        remarks = get_remarks(ac_data)
+        equipment = fp_override.equipment.as_ref().unwrap_or(&ac_data.model),
+        speed = fp_override.speed.unwrap_or(0),
+        origin = fp_override.origin.as_ref().or(airports.get_icao_from_iata(&ac_data.origin)).unwrap_or(&ac_data.origin),
+        altitude = fp_override.altitude.unwrap_or(0),
+        destination = fp_override.destination.as_ref().or(airports.get_icao_from_iata(&ac_data.destination)).unwrap_or(&ac_data.destination),
+        route = fp_override.route.as_deref().unwrap_or(""),
+        remarks = match &fp_override.remarks {
+            Some(remarks) => format!("{}, {}", get_remarks(data, airports), remarks),
+            None => get_remarks(data, airports),
+        }
     )
 }
 
+fn build_current_flightplan_string(
+    data: &TrackData,
+    airports: &Airports,
+    fp_override: Option<&FlightPlanOverride>,
+) -> String {
+    match &data.fp {
+        Some(fp) => build_flightplan_string(fp, data, airports, fp_override),
+        None => build_init_flightplan_string(data, airports, fp_override),
+    }
+}
+
 fn build_beacon_code_string(my_callsign: &str, callsign: &str, beacon_code: &str) -> String {
     format!(
         "#PCSERVER:{}:CCP:BC:{}:{}\r\n",
@@ -125,6 +248,150 @@ fn build_metar_string(callsign: &str, metar: &String) -> String {
     format!("$ARSERVER:{}:METAR:{}\r\n", callsign, metar)
 }
 
+fn build_text_message_string(callsign: &str, message: &str) -> String {
+    format!("#TMSERVER:{}:{}\r\n", callsign, message)
+}
+
+/// Commands are private messages to SERVER such as ".taf KBOS", returned as uppercase words
+fn parse_server_command(request: &str) -> Option<Vec<String>> {
+    let mut parts = request.trim().strip_prefix("#TM")?.splitn(3, ':');
+    let _from = parts.next()?;
+
+    if parts.next()? != "SERVER" {
+        return None;
+    }
+
+    let message = parts.next()?.trim();
+    if !message.starts_with('.') {
+        return None;
+    }
+
+    Some(
+        message
+            .split_whitespace()
+            .map(|x| x.to_uppercase())
+            .collect(),
+    )
+}
+
+fn build_winds_lines(winds: &WindsAloft) -> Vec<String> {
+    let profile = winds.get_profile();
+    if profile.is_empty() {
+        return vec!["No wind reports from aircraft in range".to_string()];
+    }
+
+    // Highest first, as on a winds aloft chart
+    profile
+        .iter()
+        .rev()
+        .map(|band| {
+            let temperature = band
+                .temperature
+                .map(|x| format!(" {:.0}C", x))
+                .unwrap_or_default();
+
+            format!(
+                "FL{:03} {:03.0}/{:.0}{} ({} reports)",
+                band.altitude / 100,
+                band.wind.direction,
+                band.wind.speed,
+                temperature,
+                band.observations
+            )
+        })
+        .collect()
+}
+
+fn describe_airport(icao: &str, data: &AirportData) -> String {
+    let mut line = format!("{} {}", icao, data.name);
+
+    if data.municipality != "" {
+        line += &format!(", {}", data.municipality);
+    }
+    if data.iso_country != "" {
+        line += &format!(" {}", data.iso_country);
+    }
+    if let Some(elevation) = data.elevation_ft {
+        line += &format!(", elev {}ft", elevation);
+    }
+
+    line
+}
+
+/// Looks up an ICAO or IATA code, or searches airport names
+fn build_airport_lines(airports: &Airports, query: &[&str]) -> Vec<String> {
+    if let [code] = query {
+        let icao = airports
+            .get_icao_from_iata(code)
+            .map(|x| x.as_str())
+            .unwrap_or(code);
+
+        if let Some(data) = airports.get_airport(icao) {
+            return vec![describe_airport(icao, data)];
+        }
+    }
+
+    let query = query.join(" ");
+    let found = airports.find_by_name(&query);
+    if found.is_empty() {
+        return vec![format!("No airports found for {}", query)];
+    }
+
+    let mut lines: Vec<String> = found
+        .iter()
+        .take(MAX_AIRPORT_RESULTS)
+        .map(|(icao, data)| describe_airport(icao, data))
+        .collect();
+
+    if found.len() > MAX_AIRPORT_RESULTS {
+        lines.push(format!(
+            "{} more, refine the search",
+            found.len() - MAX_AIRPORT_RESULTS
+        ));
+    }
+
+    lines
+}
+
+fn build_add_atc_string(callsign: &str) -> String {
+    format!("#AA{0:}:SERVER:{0:}:0::1:100\r\n", callsign)
+}
+
+fn build_atc_position_string(callsign: &str, frequency: &str, position: &LatLon) -> String {
+    // 118.300 is sent as 18300
+    let frequency = frequency.replacen("1", "", 1).replace(".", "");
+
+    format!(
+        "%{}:{}:4:100:1:{}:{}:0\r\n",
+        callsign, frequency, position.lat, position.lon
+    )
+}
+
+fn build_atis_strings(atis: &Atis, to: &str) -> String {
+    let callsign = atis.get_callsign();
+    let lines = atis.get_lines();
+
+    let mut response: String = lines
+        .iter()
+        .map(|line| format!("$CR{}:{}:ATIS:T:{}\r\n", callsign, to, line))
+        .collect();
+    response += &format!("$CR{}:{}:ATIS:E:{}\r\n", callsign, to, lines.len());
+
+    response
+}
+
+/// Radar clients ask an ATIS station for its text with $CQ<from>:<station>:ATIS
+fn parse_atis_request(request: &str, atis_callsign: &str) -> Option<String> {
+    let mut parts = request.trim().strip_prefix("$CQ")?.split(':');
+    let from = parts.next()?;
+
+    if parts.next()? != atis_callsign || parts.next()? != "ATIS" {
+        return None;
+    }
+
+    Some(from.to_string())
+}
+
 fn build_validate_atc_string_with_callsign(callsign: &str) -> String {
     format!("$CRSERVER:{0:}:ATC:Y:{0:}\r\n", callsign)
 }
@@ -149,6 +416,7 @@ fn build_plane_info_string(callsign: &str, to: &str, ac_data: &AircraftData) ->
 struct TrackedData {
     last_origin: String,
     last_destination: String,
+    last_fp_update: Option<Instant>,
 }
 
 struct StreamData {
@@ -156,6 +424,18 @@ struct StreamData {
     callsign: String,
 }
 
+fn default_flightplan_cache_ttl() -> u64 {
+    12
+}
+
+fn default_transition_altitude() -> i32 {
+    18000
+}
+
+fn default_atis_frequency() -> String {
+    "199.998".to_string()
+}
+
 #[derive(Deserialize, Serialize)]
 struct ConfigData {
     airport: String,
@@ -164,6 +444,34 @@ struct ConfigData {
     floor: i32,
     ceiling: i32,
     use_flightaware: bool,
+    #[serde(default)]
+    route_files: Vec<String>,
+    #[serde(default)]
+    flightplan_overrides: Option<String>,
+    #[serde(default)]
+    navdata_path: Option<String>,
+    #[serde(default = "default_flightplan_cache_ttl")]
+    flightplan_cache_ttl: u64,
+    #[serde(default)]
+    refresh_flightplan_cache: bool,
+    #[serde(default)]
+    external_providers: Vec<ExternalConfig>,
+    #[serde(default)]
+    trace_backfill: TraceBackfill,
+    #[serde(default)]
+    use_fr24_details: bool,
+    #[serde(default)]
+    atis_runways: Vec<String>,
+    #[serde(default)]
+    atis_text: String,
+    #[serde(default = "default_atis_frequency")]
+    atis_frequency: String,
+    #[serde(default)]
+    weather_source: WeatherConfig,
+    #[serde(default = "default_transition_altitude")]
+    transition_altitude: i32,
+    #[serde(default)]
+    use_geometric_altitude: bool,
 }
 
 impl Default for ConfigData {
@@ -175,6 +483,20 @@ impl Default for ConfigData {
             floor: 0,
             ceiling: 99999,
             use_flightaware: true,
+            route_files: Vec::new(),
+            flightplan_overrides: None,
+            navdata_path: None,
+            flightplan_cache_ttl: default_flightplan_cache_ttl(),
+            refresh_flightplan_cache: false,
+            external_providers: Vec::new(),
+            trace_backfill: TraceBackfill::Off,
+            use_fr24_details: false,
+            atis_runways: Vec::new(),
+            atis_text: String::new(),
+            atis_frequency: default_atis_frequency(),
+            weather_source: WeatherConfig::Noaa,
+            transition_altitude: default_transition_altitude(),
+            use_geometric_altitude: false,
         }
     }
 }
@@ -184,6 +506,27 @@ fn read_config() -> Result<ConfigData, std::io::Error> {
     Ok(serde_json::from_reader(file)?)
 }
 
+fn get_flightplan_providers(config: &ConfigData) -> Vec<Box<dyn FlightPlanProvider + Send + Sync>> {
+    let mut providers: Vec<Box<dyn FlightPlanProvider + Send + Sync>> = Vec::new();
+
+    // Local files are tried first
+    for filename in config.route_files.iter() {
+        match RouteFile::new(filename) {
+            Ok(route_file) => {
+                info!("Loaded {} routes from {}", route_file.len(), filename);
+                providers.push(Box::new(route_file));
+            }
+            Err(e) => warn!("Could not read route file {}! Reason: {:?}", filename, e),
+        }
+    }
+
+    if config.use_flightaware {
+        providers.push(Box::new(FlightAware::new()));
+    }
+
+    providers
+}
+
 fn display_msg_and_exit(msg: impl Display) {
     println!("{}\nPress the enter key to exit.", msg);
     // Wait for enter key
@@ -197,6 +540,12 @@ fn write_str(streams: &mut Vec<StreamData>, string: &str) {
     streams.retain_mut(|x| x.stream.write_all(bytes).is_ok());
 }
 
+/// Writes only to the clients logged in with the callsign
+fn write_str_to(streams: &mut Vec<StreamData>, callsign: &str, string: &str) {
+    let bytes = string.as_bytes();
+    streams.retain_mut(|x| x.callsign != callsign || x.stream.write_all(bytes).is_ok());
+}
+
 fn main() {
     // Setup logging
     TermLogger::init(
@@ -239,9 +588,19 @@ fn main() {
         }
     };
 
+    // The bundled file only has positions and codes
+    match download_airport_data(AIRPORT_DATA_FILENAME) {
+        Ok(true) => info!("Downloaded airport data to {}", AIRPORT_DATA_FILENAME),
+        Ok(false) => {}
+        Err(e) => warn!(
+            "Could not download airport data, names and elevations won't be available! Reason: {:?}",
+            e
+        ),
+    }
+
     // Load airports
     let airports = match Airports::new(AIRPORT_DATA_FILENAME) {
-        Ok(a) => a,
+        Ok(a) => Arc::new(a),
         Err(e) => {
             display_msg_and_exit(format!("Could not read airports.dat! Reason: {}", e));
             return;
@@ -259,9 +618,51 @@ fn main() {
         }
     };
 
+    let navdata = config
+        .navdata_path
+        .as_ref()
+        .and_then(|path| match NavData::load(path) {
+            Ok(n) => Some(Arc::new(n)),
+            Err(e) => {
+                warn!("Could not load nav data from {}! Reason: {:?}", path, e);
+                None
+            }
+        });
+
+    let radar_center = bounds.center();
+    let field_elevation = airports.get_elevation(&config.airport);
+
+    // Runways, optional since the file isn't bundled
+    let mut runways = match Runways::new(RUNWAY_DATA_FILENAME, &config.airport, field_elevation) {
+        Ok(r) if !r.get_ends().is_empty() => Some(r),
+        Ok(_) => {
+            warn!("No runways found for {}", config.airport);
+            None
+        }
+        Err(e) => {
+            warn!("Could not read {}! Reason: {}", RUNWAY_DATA_FILENAME, e);
+            None
+        }
+    };
+
+    let mut atis = Atis::new(
+        &config.airport,
+        config.atis_runways.clone(),
+        config.atis_text.clone(),
+    );
+
     // Weather
-    let weather = noaa::NoaaWeather::new();
+    let mut weather = Weather::new(config.weather_source.create_source(), airports.clone());
+    weather.watch(&config.airport);
     weather.run();
+    // Latest altimeter setting at the configured airport
+    let mut altimeter: Option<f32> = None;
+
+    // Flight plan overrides
+    let mut overrides = config
+        .flightplan_overrides
+        .as_ref()
+        .map(|filename| FlightPlanOverrides::new(filename));
 
     loop {
         info!("Waiting for connection...");
@@ -269,16 +670,36 @@ fn main() {
         let mut streams: Vec<StreamData> = Vec::new();
 
         // Instantiate main tracker
-        let mut tracker = Tracker::new(&bounds, config.floor, config.ceiling);
+        let mut tracker = Tracker::new(
+            &bounds,
+            config.floor,
+            config.ceiling,
+            &config.external_providers,
+            airports.clone(),
+        );
         // Start loops to listen for data
-        if config.use_flightaware {
-            tracker.run_faware();
+        if config.flightplan_cache_ttl > 0 {
+            tracker.use_flightplan_cache(FlightPlanCache::load(
+                FLIGHTPLAN_CACHE_FILENAME,
+                config.flightplan_cache_ttl,
+                config.refresh_flightplan_cache,
+            ));
+        }
+        if let Some(navdata) = &navdata {
+            tracker.use_navdata(navdata.clone());
+        }
+        tracker.run_flightplans(get_flightplan_providers(&config));
+        if config.use_fr24_details {
+            tracker.run_fr24_details();
         }
+        tracker.run_traces(config.trace_backfill);
         tracker.run();
 
         // Map to keep track of data already injected
         let mut injected_tracker: HashMap<String, TrackedData> = HashMap::new();
         let mut current_atc_callsign = String::new();
+        // Who asked for the TAF of each station
+        let mut taf_requests: HashMap<String, HashSet<String>> = HashMap::new();
         let mut did_init = false;
         let mut timer: Option<Instant> = None;
         let buffer_timer = Instant::now();
@@ -292,6 +713,9 @@ fn main() {
                     .write("$DISERVER:CLIENT:VATSIM FSD V3.14:\r\n".as_bytes())
                     .ok();
                 stream.set_nonblocking(true).ok();
+                stream
+                    .write(build_add_atc_string(&atis.get_callsign()).as_bytes())
+                    .ok();
                 streams.push(StreamData {
                     stream,
                     callsign: String::new(),
@@ -318,6 +742,10 @@ fn main() {
             // Process aircraft
             tracker.step();
 
+            if let Some(overrides) = overrides.as_mut() {
+                overrides.reload_if_changed();
+            }
+
             let should_update_position =
                 timer.is_none() || timer.unwrap().elapsed().as_secs_f32() >= 5.0;
 
@@ -334,25 +762,60 @@ fn main() {
                     let should_interpolate = !aircraft.ac_data.is_on_ground
                         && aircraft.at_last_position_update.elapsed().as_secs() < 20;
 
+                    let altitude = if aircraft.ac_data.is_on_ground {
+                        get_ground_altitude(&aircraft.ac_data, &airports)
+                    } else {
+                        aircraft.ac_data.get_indicated_altitude(
+                            altimeter,
+                            config.transition_altitude,
+                            config.use_geometric_altitude,
+                        )
+                    };
                     write_str(
                         &mut streams,
-                        &build_aircraft_string(aircraft, should_interpolate),
+                        &build_aircraft_string(aircraft, should_interpolate, altitude),
                     );
 
+                    if let Some(runways) = &mut runways {
+                        runways.update(&aircraft.ac_data);
+                    }
+
                     // Give the aircraft an initial flight plan
                     let metadata_was_updated = (aircraft.ac_data.origin != ""
                         && tracked.last_origin != aircraft.ac_data.origin)
                         || (aircraft.ac_data.destination != ""
                             && tracked.last_destination != aircraft.ac_data.destination);
 
+                    let fp_override = overrides.as_ref().and_then(|x| {
+                        x.get(&aircraft.ac_data.callsign, &aircraft.ac_data.registration)
+                    });
+
                     if metadata_was_updated && aircraft.fp.is_none() {
                         write_str(
                             &mut streams,
-                            &build_init_flightplan_string(&aircraft.ac_data, &airports),
+                            &build_init_flightplan_string(aircraft, &airports, fp_override),
                         );
 
                         tracked.last_origin = aircraft.ac_data.origin.clone();
                         tracked.last_destination = aircraft.ac_data.destination.clone();
+                        tracked.last_fp_update = Some(Instant::now());
+                    }
+
+                    // Keep the distance to go and ETA of arrivals up to date
+                    let is_refresh_due = tracked
+                        .last_fp_update
+                        .map(|x| x.elapsed() >= ETA_REFRESH_INTERVAL)
+                        .unwrap_or(true);
+
+                    if is_refresh_due
+                        && aircraft.is_arriving_at(&radar_center, config.range as f32, &airports)
+                    {
+                        write_str(
+                            &mut streams,
+                            &build_current_flightplan_string(aircraft, &airports, fp_override),
+                        );
+
+                        tracked.last_fp_update = Some(Instant::now());
                     }
                 }
             }
@@ -362,6 +825,33 @@ fn main() {
                 // Reset position update timer
                 timer = Some(Instant::now());
 
+                write_str(
+                    &mut streams,
+                    &build_atc_position_string(
+                        &atis.get_callsign(),
+                        &config.atis_frequency,
+                        &radar_center,
+                    ),
+                );
+
+                if let Some(runways) = &runways {
+                    let arrivals = runways.get_active(RunwayUse::Arrival);
+                    let departures = runways.get_active(RunwayUse::Departure);
+
+                    if atis.update_runways(arrivals, departures) {
+                        if let Some(active) = atis.get_active_runways() {
+                            info!("Runways in use: {}", active);
+                            write_str(
+                                &mut streams,
+                                &build_text_message_string(
+                                    &current_atc_callsign,
+                                    &format!("Runways in use: {}", active),
+                                ),
+                            );
+                        }
+                    }
+                }
+
                 // Manage buffering
                 if tracker.is_buffering() {
                     let elaspsed = buffer_timer.elapsed().as_secs();
@@ -398,6 +888,61 @@ fn main() {
 
                 if client_request.trim() != "" {
                     for request in client_request.split("\n") {
+                        if let Some(from) = parse_atis_request(request, &atis.get_callsign()) {
+                            stream
+                                .write(build_atis_strings(&atis, &from).as_bytes())
+                                .ok();
+                            continue;
+                        }
+
+                        if let Some(command) = parse_server_command(request) {
+                            match command.iter().map(|x| x.as_str()).collect::<Vec<&str>>()[..] {
+                                [".TAF", station] => {
+                                    info!("Getting TAF for {}", station);
+                                    taf_requests
+                                        .entry(station.to_string())
+                                        .or_default()
+                                        .insert(callsign.clone());
+                                    weather.request_taf(station);
+                                }
+                                [".APT", ref query @ ..] if !query.is_empty() => {
+                                    for line in build_airport_lines(&airports, query) {
+                                        stream
+                                            .write(
+                                                build_text_message_string(callsign, &line)
+                                                    .as_bytes(),
+                                            )
+                                            .ok();
+                                    }
+                                }
+                                [".RWY"] => {
+                                    let line = match &runways {
+                                        Some(_) => atis
+                                            .get_active_runways()
+                                            .unwrap_or("No runway use seen yet".to_string()),
+                                        None => "No runway data loaded".to_string(),
+                                    };
+                                    stream
+                                        .write(
+                                            build_text_message_string(callsign, &line).as_bytes(),
+                                        )
+                                        .ok();
+                                }
+                                [".WINDS"] => {
+                                    for line in build_winds_lines(tracker.get_winds()) {
+                                        stream
+                                            .write(
+                                                build_text_message_string(callsign, &line)
+                                                    .as_bytes(),
+                                            )
+                                            .ok();
+                                    }
+                                }
+                                _ => (),
+                            }
+                            continue;
+                        }
+
                         let packet = match Parser::parse(request) {
                             Some(p) => p,
                             None => continue,
@@ -431,29 +976,27 @@ fn main() {
                             }
                             PacketTypes::ClientQuery(cq) => match cq.payload {
                                 ClientQueryPayload::FlightPlan(callsign) => {
+                                    tracker.prioritize_flightplan(&callsign);
+
                                     let data = match tracker.get_data_for_callsign(&callsign) {
                                         Some(d) => d,
                                         None => continue,
                                     };
 
-                                    if let Some(fp) = &data.fp {
-                                        stream
-                                            .write(
-                                                build_flightplan_string(fp, &data.ac_data)
-                                                    .as_bytes(),
-                                            )
-                                            .ok();
-                                    } else {
-                                        stream
-                                            .write(
-                                                &build_init_flightplan_string(
-                                                    &data.ac_data,
-                                                    &airports,
-                                                )
-                                                .as_bytes(),
+                                    let fp_override = overrides.as_ref().and_then(|x| {
+                                        x.get(&data.ac_data.callsign, &data.ac_data.registration)
+                                    });
+
+                                    stream
+                                        .write(
+                                            build_current_flightplan_string(
+                                                data,
+                                                &airports,
+                                                fp_override,
                                             )
-                                            .ok();
-                                    }
+                                            .as_bytes(),
+                                        )
+                                        .ok();
 
                                     // Not squawking anything... will have duplicates if we assign an empty code
                                     if data.ac_data.squawk != "0000" {
@@ -506,15 +1049,143 @@ fn main() {
             }
 
             // Step stuff
-            if let Some(Ok(metar)) = weather.get_next_weather() {
-                info!("Got metar {}", metar);
+            weather.step();
+            while let Some(metar) = weather.get_next_weather() {
+                info!("Got metar {}", metar.raw);
+                if metar.station == atis.airport {
+                    altimeter = metar.altimeter_inhg.or(altimeter);
+                    if atis.update_metar(&metar) {
+                        info!("ATIS is now information {}", atis.get_letter());
+                    }
+                }
+                write_str(
+                    &mut streams,
+                    &build_metar_string(&current_atc_callsign, &metar.raw),
+                );
+            }
+            while let Some(nearest) = weather.get_next_nearest_weather() {
+                info!(
+                    "Using metar from {} for {}",
+                    nearest.metar.station, nearest.requested
+                );
+                if nearest.requested == atis.airport {
+                    altimeter = nearest.metar.altimeter_inhg.or(altimeter);
+                }
                 write_str(
                     &mut streams,
-                    &build_metar_string(&current_atc_callsign, &metar),
+                    &build_text_message_string(
+                        &current_atc_callsign,
+                        &format!(
+                            "No METAR for {}, showing {} ({:.0}nm away)",
+                            nearest.requested, nearest.metar.station, nearest.distance
+                        ),
+                    ),
+                );
+                write_str(
+                    &mut streams,
+                    &build_metar_string(&current_atc_callsign, &nearest.metar.raw),
                 );
             }
+            while let Some(taf) = weather.get_next_taf() {
+                info!("Got TAF {}", taf.raw);
+                for callsign in taf_requests.remove(&taf.station).unwrap_or_default() {
+                    for line in taf.get_lines() {
+                        write_str_to(
+                            &mut streams,
+                            &callsign,
+                            &build_text_message_string(&callsign, &line),
+                        );
+                    }
+                }
+            }
+            while let Some(station) = weather.get_next_missing_taf() {
+                for callsign in taf_requests.remove(&station).unwrap_or_default() {
+                    write_str_to(
+                        &mut streams,
+                        &callsign,
+                        &build_text_message_string(&callsign, &format!("No TAF for {}", station)),
+                    );
+                }
+            }
 
             sleep(std::time::Duration::from_millis(10));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_atc_frequency() {
+        let position = LatLon {
+            lat: 42.36,
+            lon: -71.0,
+        };
+
+        assert_eq!(
+            build_atc_position_string("KBOS_ATIS", "135.000", &position),
+            "%KBOS_ATIS:35000:4:100:1:42.36:-71:0\r\n"
+        );
+        // Only the leading 1 is dropped
+        assert_eq!(
+            build_atc_position_string("KBOS_ATIS", "121.100", &position),
+            "%KBOS_ATIS:21100:4:100:1:42.36:-71:0\r\n"
+        );
+    }
+
+    #[test]
+    fn shows_ground_traffic_at_field_elevation() {
+        let airports = Airports::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/airports/full.csv"
+        ))
+        .unwrap();
+        let aircraft = |latitude: f32, longitude: f32, altitude: i32| AircraftData {
+            latitude,
+            longitude,
+            altitude,
+            is_on_ground: true,
+            ..Default::default()
+        };
+
+        // Parked at KBED, away from the configured airport
+        assert_eq!(
+            get_ground_altitude(&aircraft(42.47, -71.289, 0), &airports),
+            133
+        );
+        assert_eq!(
+            get_ground_altitude(&aircraft(42.47, -71.289, -200), &airports),
+            133
+        );
+        // No airport around, left as reported
+        assert_eq!(
+            get_ground_altitude(&aircraft(10.0, 10.0, 25), &airports),
+            25
+        );
+    }
+
+    #[test]
+    fn parses_atis_requests() {
+        assert_eq!(
+            parse_atis_request("$CQBOS_APP:KBOS_ATIS:ATIS\r", "KBOS_ATIS"),
+            Some("BOS_APP".to_string())
+        );
+        // Someone else's ATIS
+        assert_eq!(
+            parse_atis_request("$CQBOS_APP:KJFK_ATIS:ATIS", "KBOS_ATIS"),
+            None
+        );
+        // Other client queries
+        assert_eq!(
+            parse_atis_request("$CQBOS_APP:KBOS_ATIS:RN", "KBOS_ATIS"),
+            None
+        );
+        assert_eq!(parse_atis_request("$CQBOS_APP", "KBOS_ATIS"), None);
+        assert_eq!(
+            parse_atis_request("#TMBOS_APP:KBOS_ATIS:ATIS", "KBOS_ATIS"),
+            None
+        );
+    }
+}
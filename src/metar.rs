@@ -0,0 +1,379 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const HPA_PER_INHG: f32 = 33.8639;
+const METERS_PER_STATUTE_MILE: f32 = 1609.34;
+const KNOTS_PER_MPS: f32 = 1.94384;
+const KNOTS_PER_KMH: f32 = 0.539957;
+
+lazy_static! {
+    static ref TIME_REGEX: Regex = Regex::new(r"^(\d{2})(\d{2})(\d{2})Z$").unwrap();
+    static ref WIND_REGEX: Regex =
+        Regex::new(r"^(\d{3}|VRB)(\d{2,3})(?:G(\d{2,3}))?(KT|MPS|KMH)$").unwrap();
+    static ref WIND_VARIATION_REGEX: Regex = Regex::new(r"^(\d{3})V(\d{3})$").unwrap();
+    static ref VISIBILITY_METERS_REGEX: Regex = Regex::new(r"^(\d{4})(NDV)?$").unwrap();
+    static ref VISIBILITY_MILES_REGEX: Regex =
+        Regex::new(r"^([PM])?(?:(\d+)|(\d+)/(\d+))SM$").unwrap();
+    static ref RVR_REGEX: Regex =
+        Regex::new(r"^R(\d{2}[LRC]?)/[PM]?(\d{4})(?:V[PM]?(\d{4}))?(FT)?(?:/?[UDN])?$").unwrap();
+    // A descriptor needs a phenomenon after it, except for thunderstorms and showers in the vicinity
+    static ref WEATHER_REGEX: Regex = Regex::new(
+        r"^(?:(?:[+-]|VC)?(?:MI|PR|BC|DR|BL|SH|TS|FZ)?(?:DZ|RA|SN|SG|IC|PL|GR|GS|UP|BR|FG|FU|VA|DU|SA|HZ|PY|PO|SQ|FC|SS|DS)+|[+-]?TS|VC(?:TS|SH))$"
+    )
+    .unwrap();
+    static ref CLOUD_REGEX: Regex =
+        Regex::new(r"^(FEW|SCT|BKN|OVC|VV)(\d{3}|///)(CB|TCU)?$").unwrap();
+    static ref TEMPERATURE_REGEX: Regex = Regex::new(r"^(M?\d{2})/(M?\d{2})?$").unwrap();
+    static ref ALTIMETER_REGEX: Regex = Regex::new(r"^([AQ])(\d{4})$").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wind {
+    // None when variable
+    pub direction: Option<u32>,
+    // Knots
+    pub speed: u32,
+    pub gust: Option<u32>,
+    // Range the direction varies between
+    pub variation: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunwayVisualRange {
+    pub runway: String,
+    // Feet
+    pub visual_range: u32,
+    pub variable_to: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloudCover {
+    Few,
+    Scattered,
+    Broken,
+    Overcast,
+    VerticalVisibility,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudLayer {
+    pub cover: CloudCover,
+    // Feet above ground, None if not reported
+    pub base: Option<u32>,
+    // CB or TCU
+    pub cloud_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlightCategory {
+    Vfr,
+    Mvfr,
+    Ifr,
+    Lifr,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metar {
+    pub station: String,
+    pub raw: String,
+    // Day of month, hour and minute in UTC
+    pub time: Option<(u32, u32, u32)>,
+    pub wind: Option<Wind>,
+    // Statute miles
+    pub visibility: Option<f32>,
+    pub rvr: Vec<RunwayVisualRange>,
+    // Present weather groups such as -RA or +TSRA
+    pub weather: Vec<String>,
+    pub clouds: Vec<CloudLayer>,
+    // Degrees Celsius
+    pub temperature: Option<i32>,
+    pub dewpoint: Option<i32>,
+    pub altimeter_inhg: Option<f32>,
+    pub altimeter_hpa: Option<f32>,
+}
+
+fn parse_temperature(text: &str) -> Option<i32> {
+    match text.strip_prefix('M') {
+        Some(t) => t.parse::<i32>().ok().map(|x| -x),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_speed(speed: u32, unit: &str) -> u32 {
+    match unit {
+        "MPS" => (speed as f32 * KNOTS_PER_MPS).round() as u32,
+        "KMH" => (speed as f32 * KNOTS_PER_KMH).round() as u32,
+        _ => speed,
+    }
+}
+
+impl Metar {
+    /// Decodes the main body of a METAR, groups that aren't understood are skipped
+    pub fn parse(raw: &str) -> Self {
+        let mut metar = Metar {
+            raw: raw.trim().to_string(),
+            ..Default::default()
+        };
+
+        let tokens: Vec<&str> = raw
+            .split_whitespace()
+            .skip_while(|x| *x == "METAR" || *x == "SPECI")
+            // Remarks and trends don't describe the current conditions
+            .take_while(|x| !matches!(*x, "RMK" | "TEMPO" | "BECMG" | "NOSIG"))
+            .collect();
+
+        if let Some(station) = tokens.first() {
+            metar.station = station.to_string();
+            metar.parse_groups(&tokens[1..]);
+        }
+
+        metar
+    }
+
+    /// Fills in conditions from weather groups, also used for TAF forecast periods
+    pub fn parse_groups(&mut self, tokens: &[&str]) {
+        let metar = self;
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = tokens[i];
+            i += 1;
+
+            if let Some(c) = TIME_REGEX.captures(token) {
+                metar.time = Some((
+                    c[1].parse().unwrap(),
+                    c[2].parse().unwrap(),
+                    c[3].parse().unwrap(),
+                ));
+            } else if let Some(c) = WIND_REGEX.captures(token) {
+                let unit = &c[4];
+                metar.wind = Some(Wind {
+                    direction: c[1].parse().ok(),
+                    speed: parse_speed(c[2].parse().unwrap(), unit),
+                    gust: c
+                        .get(3)
+                        .map(|x| parse_speed(x.as_str().parse().unwrap(), unit)),
+                    variation: None,
+                });
+            } else if let Some(c) = WIND_VARIATION_REGEX.captures(token) {
+                if let Some(wind) = metar.wind.as_mut() {
+                    wind.variation = Some((c[1].parse().unwrap(), c[2].parse().unwrap()));
+                }
+            } else if token == "CAVOK" {
+                // Visibility of 10km or more and no cloud below 5000ft
+                metar.visibility = Some(10000.0 / METERS_PER_STATUTE_MILE);
+            } else if let Some(c) = VISIBILITY_METERS_REGEX.captures(token) {
+                let meters: f32 = c[1].parse().unwrap();
+                metar.visibility = Some(meters / METERS_PER_STATUTE_MILE);
+            } else if let Some(c) = VISIBILITY_MILES_REGEX.captures(token) {
+                let mut miles = match c.get(2) {
+                    Some(whole) => whole.as_str().parse().unwrap(),
+                    None => c[3].parse::<f32>().unwrap() / c[4].parse::<f32>().unwrap(),
+                };
+
+                // Whole miles are a separate group, as in 1 1/2SM
+                if let Some(whole) = i
+                    .checked_sub(2)
+                    .and_then(|x| tokens.get(x))
+                    .filter(|_| c.get(3).is_some())
+                    .and_then(|x| x.parse::<f32>().ok())
+                {
+                    miles += whole;
+                }
+
+                metar.visibility = Some(miles);
+            } else if let Some(c) = RVR_REGEX.captures(token) {
+                let is_feet = c.get(4).is_some();
+                let to_feet = |x: u32| {
+                    if is_feet {
+                        x
+                    } else {
+                        (x as f32 * 3.28084).round() as u32
+                    }
+                };
+
+                metar.rvr.push(RunwayVisualRange {
+                    runway: c[1].to_string(),
+                    visual_range: to_feet(c[2].parse().unwrap()),
+                    variable_to: c.get(3).map(|x| to_feet(x.as_str().parse().unwrap())),
+                });
+            } else if let Some(c) = CLOUD_REGEX.captures(token) {
+                metar.clouds.push(CloudLayer {
+                    cover: match &c[1] {
+                        "FEW" => CloudCover::Few,
+                        "SCT" => CloudCover::Scattered,
+                        "BKN" => CloudCover::Broken,
+                        "OVC" => CloudCover::Overcast,
+                        _ => CloudCover::VerticalVisibility,
+                    },
+                    base: c[2].parse::<u32>().ok().map(|x| x * 100),
+                    cloud_type: c.get(3).map(|x| x.as_str().to_string()),
+                });
+            } else if let Some(c) = TEMPERATURE_REGEX.captures(token) {
+                metar.temperature = parse_temperature(&c[1]);
+                metar.dewpoint = c.get(2).and_then(|x| parse_temperature(x.as_str()));
+            } else if let Some(c) = ALTIMETER_REGEX.captures(token) {
+                let value: f32 = c[2].parse().unwrap();
+                let (inhg, hpa) = if &c[1] == "A" {
+                    (value / 100.0, value / 100.0 * HPA_PER_INHG)
+                } else {
+                    (value / HPA_PER_INHG, value)
+                };
+
+                metar.altimeter_inhg = Some(inhg);
+                metar.altimeter_hpa = Some(hpa);
+            } else if WEATHER_REGEX.is_match(token) {
+                metar.weather.push(token.to_string());
+            }
+        }
+    }
+
+    /// Lowest broken, overcast or obscured layer
+    pub fn get_ceiling(&self) -> Option<u32> {
+        self.clouds
+            .iter()
+            .filter(|x| {
+                matches!(
+                    x.cover,
+                    CloudCover::Broken | CloudCover::Overcast | CloudCover::VerticalVisibility
+                )
+            })
+            .filter_map(|x| x.base)
+            .min()
+    }
+
+    /// FAA flight category from the ceiling and visibility
+    pub fn get_flight_category(&self) -> FlightCategory {
+        let ceiling = self.get_ceiling().unwrap_or(u32::MAX);
+        let visibility = self.visibility.unwrap_or(f32::MAX);
+
+        if ceiling < 500 || visibility < 1.0 {
+            FlightCategory::Lifr
+        } else if ceiling < 1000 || visibility < 3.0 {
+            FlightCategory::Ifr
+        } else if ceiling <= 3000 || visibility <= 5.0 {
+            FlightCategory::Mvfr
+        } else {
+            FlightCategory::Vfr
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_us_metar() {
+        let metar = Metar::parse(
+            "KBOS 181454Z 27015G25KT 240V300 10SM FEW050 BKN250 18/M02 A2992 RMK AO2 SLP132",
+        );
+
+        assert_eq!(metar.station, "KBOS");
+        assert_eq!(metar.time, Some((18, 14, 54)));
+        assert_eq!(
+            metar.wind,
+            Some(Wind {
+                direction: Some(270),
+                speed: 15,
+                gust: Some(25),
+                variation: Some((240, 300)),
+            })
+        );
+        assert_close(metar.visibility.unwrap(), 10.0);
+        assert_eq!(metar.clouds.len(), 2);
+        assert_eq!(metar.clouds[1].cover, CloudCover::Broken);
+        assert_eq!(metar.clouds[1].base, Some(25000));
+        assert_eq!(metar.temperature, Some(18));
+        assert_eq!(metar.dewpoint, Some(-2));
+        assert_close(metar.altimeter_inhg.unwrap(), 29.92);
+        assert_close(metar.altimeter_hpa.unwrap(), 1013.21);
+        assert_eq!(metar.get_flight_category(), FlightCategory::Vfr);
+    }
+
+    #[test]
+    fn parses_icao_metar() {
+        let metar =
+            Metar::parse("METAR EGLL 181450Z 05008MPS 9999 -RA SCT014 OVC030CB 12/11 Q1003 NOSIG");
+
+        assert_eq!(metar.station, "EGLL");
+        let wind = metar.wind.clone().unwrap();
+        assert_eq!(wind.direction, Some(50));
+        assert_eq!(wind.speed, 16);
+        assert_close(metar.visibility.unwrap(), 6.21);
+        assert_eq!(metar.weather, vec!["-RA"]);
+        assert_eq!(metar.clouds[1].cloud_type.as_deref(), Some("CB"));
+        assert_eq!(metar.get_ceiling(), Some(3000));
+        assert_close(metar.altimeter_hpa.unwrap(), 1003.0);
+        assert_close(metar.altimeter_inhg.unwrap(), 29.62);
+        assert_eq!(metar.get_flight_category(), FlightCategory::Mvfr);
+    }
+
+    #[test]
+    fn parses_fractional_visibility_and_rvr() {
+        let metar =
+            Metar::parse("KJFK 181451Z VRB03KT 1 1/2SM R04R/2200V4000FT BR VV004 M01/M01 A3001");
+
+        let wind = metar.wind.clone().unwrap();
+        assert_eq!(wind.direction, None);
+        assert_eq!(wind.speed, 3);
+        assert_close(metar.visibility.unwrap(), 1.5);
+        assert_eq!(
+            metar.rvr,
+            vec![RunwayVisualRange {
+                runway: "04R".to_string(),
+                visual_range: 2200,
+                variable_to: Some(4000),
+            }]
+        );
+        assert_eq!(metar.weather, vec!["BR"]);
+        assert_eq!(metar.temperature, Some(-1));
+        assert_eq!(metar.get_ceiling(), Some(400));
+        assert_eq!(metar.get_flight_category(), FlightCategory::Lifr);
+    }
+
+    #[test]
+    fn parses_cavok_and_metric_rvr() {
+        let metar = Metar::parse("LFPG 181500Z 21010KT CAVOK 22/10 Q1018");
+
+        assert!(metar.clouds.is_empty());
+        assert!(metar.visibility.unwrap() > 6.0);
+        assert_eq!(metar.get_flight_category(), FlightCategory::Vfr);
+
+        let metar = Metar::parse("EDDF 181500Z 00000KT 0400 R25L/0550N FG VV001 05/05 Q1020");
+        assert_eq!(metar.rvr[0].runway, "25L");
+        assert_eq!(metar.rvr[0].visual_range, 1804);
+        assert_eq!(metar.get_flight_category(), FlightCategory::Lifr);
+    }
+
+    #[test]
+    fn parses_present_weather() {
+        let metar = Metar::parse(
+            "KMIA 181453Z 09012KT 5SM +TSRA TS VCSH VCTS -SHRA FZFG BCFG VC SH - + TSX 26/24 A2998",
+        );
+
+        assert_eq!(
+            metar.weather,
+            vec!["+TSRA", "TS", "VCSH", "VCTS", "-SHRA", "FZFG", "BCFG"]
+        );
+    }
+
+    #[test]
+    fn skips_unknown_groups() {
+        let metar = Metar::parse("KXYZ 181454Z AUTO 00000KT ///// 10SM CLR 20/10 A3000 $");
+
+        assert_eq!(metar.station, "KXYZ");
+        assert!(metar.weather.is_empty());
+        assert!(metar.clouds.is_empty());
+        assert_eq!(metar.temperature, Some(20));
+    }
+}
@@ -0,0 +1,451 @@
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::util::LatLon;
+
+// X-Plane 11 navigation data files, as shipped with the sim or by Navigraph
+const FIX_FILENAME: &str = "earth_fix.dat";
+const NAV_FILENAME: &str = "earth_nav.dat";
+const AWY_FILENAME: &str = "earth_awy.dat";
+
+lazy_static! {
+    // N0450F350, M082F370, K0830S1130...
+    static ref SPEED_LEVEL_REGEX: Regex = Regex::new(r"^[NKM]\d{3,4}[FASM]\d{3,4}$").unwrap();
+    // 45N050W or 4530N05000W
+    static ref COORDINATE_REGEX: Regex =
+        Regex::new(r"^(\d{2})(\d{2})?([NS])(\d{3})(\d{2})?([EW])$").unwrap();
+    // Named procedures end with a version digit and an optional letter
+    static ref PROCEDURE_REGEX: Regex = Regex::new(r"^[A-Z]{2,6}\d[A-Z]?$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixKind {
+    Waypoint,
+    Vor,
+    Ndb,
+    Coordinate,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub ident: String,
+    pub region: String,
+    pub kind: FixKind,
+    pub position: LatLon,
+}
+
+impl Fix {
+    fn get_key(&self) -> String {
+        get_key(&self.ident, &self.region)
+    }
+}
+
+fn get_key(ident: &str, region: &str) -> String {
+    format!("{}/{}", ident, region)
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteFix {
+    pub fix: Fix,
+    // Airway used to reach this fix, None if direct
+    pub via: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsedRoute {
+    pub sid: Option<String>,
+    pub star: Option<String>,
+    pub fixes: Vec<RouteFix>,
+    // Parts of the route that couldn't be matched to anything
+    pub unknown: Vec<String>,
+}
+
+pub struct NavData {
+    // Identifiers aren't unique worldwide
+    fixes: HashMap<String, Vec<Fix>>,
+    // Airway name to the fixes reachable from each fix
+    airways: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+fn read_lines(path: &Path) -> Result<impl Iterator<Item = String>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+
+    // The first two lines are the byte order and version headers, 99 ends the file
+    Ok(reader
+        .lines()
+        .skip(2)
+        .filter_map(|x| x.ok())
+        .take_while(|x| x.trim() != "99"))
+}
+
+fn parse_position(lat: &str, lon: &str) -> Option<LatLon> {
+    Some(LatLon {
+        lat: lat.parse().ok()?,
+        lon: lon.parse().ok()?,
+    })
+}
+
+fn parse_fix_line(line: &str) -> Option<Fix> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    Some(Fix {
+        position: parse_position(parts.get(0)?, parts.get(1)?)?,
+        ident: parts.get(2)?.to_string(),
+        region: parts.get(4).unwrap_or(&"").to_string(),
+        kind: FixKind::Waypoint,
+    })
+}
+
+fn parse_nav_line(line: &str) -> Option<Fix> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    let kind = match *parts.get(0)? {
+        "2" => FixKind::Ndb,
+        "3" => FixKind::Vor,
+        // ILS, markers and DMEs aren't used in routes
+        _ => return None,
+    };
+
+    Some(Fix {
+        position: parse_position(parts.get(1)?, parts.get(2)?)?,
+        ident: parts.get(7)?.to_string(),
+        region: parts.get(9).unwrap_or(&"").to_string(),
+        kind,
+    })
+}
+
+/// Parses coordinates written as 45N050W or 4530N05000W
+fn parse_coordinate(token: &str) -> Option<Fix> {
+    let captures = COORDINATE_REGEX.captures(token)?;
+    let get_number = |i: usize| -> f32 {
+        captures
+            .get(i)
+            .and_then(|x| x.as_str().parse().ok())
+            .unwrap_or_default()
+    };
+
+    let mut lat = get_number(1) + get_number(2) / 60.0;
+    let mut lon = get_number(4) + get_number(5) / 60.0;
+
+    if &captures[3] == "S" {
+        lat = -lat;
+    }
+    if &captures[6] == "W" {
+        lon = -lon;
+    }
+
+    Some(Fix {
+        ident: token.to_string(),
+        region: String::new(),
+        kind: FixKind::Coordinate,
+        position: LatLon { lat, lon },
+    })
+}
+
+impl NavData {
+    pub fn load(directory: &str) -> Result<Self, Error> {
+        let directory = Path::new(directory);
+        let mut fixes: HashMap<String, Vec<Fix>> = HashMap::new();
+
+        let fix_lines =
+            read_lines(&directory.join(FIX_FILENAME))?.filter_map(|x| parse_fix_line(&x));
+        let nav_lines =
+            read_lines(&directory.join(NAV_FILENAME))?.filter_map(|x| parse_nav_line(&x));
+
+        for fix in fix_lines.chain(nav_lines) {
+            fixes.entry(fix.ident.clone()).or_default().push(fix);
+        }
+
+        let mut airways: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+
+        for line in read_lines(&directory.join(AWY_FILENAME))? {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 11 {
+                continue;
+            }
+
+            let from = get_key(parts[0], parts[1]);
+            let to = get_key(parts[3], parts[4]);
+            // N is both ways, F only from the first fix, B only from the second
+            let direction = parts[6];
+
+            // Segments shared by several airways list all of them joined with a dash
+            for name in parts[10].split('-') {
+                let airway = airways.entry(name.to_string()).or_default();
+
+                if direction != "B" {
+                    airway.entry(from.clone()).or_default().push(to.clone());
+                }
+                if direction != "F" {
+                    airway.entry(to.clone()).or_default().push(from.clone());
+                }
+            }
+        }
+
+        info!(
+            "Loaded {} fixes and {} airways from {}",
+            fixes.len(),
+            airways.len(),
+            directory.display()
+        );
+
+        Ok(Self { fixes, airways })
+    }
+
+    /// Fix with the identifier closest to the given position
+    fn get_nearest_fix(&self, ident: &str, near: &LatLon) -> Option<&Fix> {
+        self.fixes.get(ident)?.iter().min_by(|a, b| {
+            a.position
+                .distance_to(near)
+                .partial_cmp(&b.position.distance_to(near))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+
+    fn get_fix_by_key(&self, key: &str) -> Option<&Fix> {
+        let ident = key.split('/').next()?;
+        self.fixes.get(ident)?.iter().find(|x| x.get_key() == key)
+    }
+
+    /// Fixes along the airway after entry up to and including exit
+    fn expand_airway(&self, airway: &str, entry: &Fix, exit: &str) -> Option<Vec<Fix>> {
+        let graph = self.airways.get(airway)?;
+
+        // Already at the exit, nothing to add
+        if entry.ident == exit {
+            return Some(Vec::new());
+        }
+
+        // The entry may have been matched to a fix with the same name in another region
+        let start = if graph.contains_key(&entry.get_key()) {
+            entry.get_key()
+        } else {
+            graph
+                .keys()
+                .find(|x| x.split('/').next() == Some(&entry.ident))?
+                .clone()
+        };
+
+        let mut previous: HashMap<String, String> = HashMap::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        visited.insert(start.clone());
+        queue.push_back(start.clone());
+
+        while let Some(key) = queue.pop_front() {
+            if key.split('/').next() == Some(exit) {
+                let mut path = vec![key.clone()];
+                while let Some(prev) = previous.get(path.last()?) {
+                    if *prev == start {
+                        break;
+                    }
+                    path.push(prev.clone());
+                }
+
+                return path
+                    .iter()
+                    .rev()
+                    .map(|x| self.get_fix_by_key(x).cloned())
+                    .collect();
+            }
+
+            for next in graph.get(&key).into_iter().flatten() {
+                if visited.insert(next.clone()) {
+                    previous.insert(next.clone(), key.clone());
+                    queue.push_back(next.clone());
+                }
+            }
+        }
+
+        None
+    }
+
+    fn is_known(&self, token: &str) -> bool {
+        self.fixes.contains_key(token) || self.airways.contains_key(token)
+    }
+
+    /// Expands a route string into fixes, starting the search for ambiguous names from the origin
+    pub fn parse_route(
+        &self,
+        route: &str,
+        origin: &str,
+        destination: &str,
+        start: LatLon,
+    ) -> ParsedRoute {
+        let tokens: Vec<&str> = route
+            .split(|c: char| c.is_whitespace() || c == '.')
+            // Speed and level changes are attached to fixes with a slash
+            .filter_map(|x| x.split('/').next())
+            .filter(|x| {
+                *x != ""
+                    && *x != "DCT"
+                    && *x != origin
+                    && *x != destination
+                    && !SPEED_LEVEL_REGEX.is_match(x)
+            })
+            .collect();
+
+        let mut parsed = ParsedRoute::default();
+        let mut position = start;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let token = tokens[i];
+            i += 1;
+
+            if let Some(fix) = parse_coordinate(token) {
+                position = fix.position.clone();
+                parsed.fixes.push(RouteFix { fix, via: None });
+                continue;
+            }
+
+            let last_fix = parsed.fixes.last().map(|x| x.fix.clone());
+
+            if let (true, Some(entry), Some(exit)) = (
+                self.airways.contains_key(token),
+                last_fix.as_ref(),
+                tokens.get(i),
+            ) {
+                if let Some(fixes) = self.expand_airway(token, entry, exit) {
+                    for fix in fixes {
+                        position = fix.position.clone();
+                        parsed.fixes.push(RouteFix {
+                            fix,
+                            via: Some(token.to_string()),
+                        });
+                    }
+                    // The exit fix was added with the airway
+                    i += 1;
+                    continue;
+                }
+            }
+
+            if let Some(fix) = self.get_nearest_fix(token, &position) {
+                position = fix.position.clone();
+                parsed.fixes.push(RouteFix {
+                    fix: fix.clone(),
+                    via: None,
+                });
+                continue;
+            }
+
+            if PROCEDURE_REGEX.is_match(token) && !self.is_known(token) {
+                // Procedures before any fix are departures, the rest arrivals
+                if parsed.fixes.is_empty() && parsed.sid.is_none() {
+                    parsed.sid = Some(token.to_string());
+                } else {
+                    parsed.star = Some(token.to_string());
+                }
+                continue;
+            }
+
+            parsed.unknown.push(token.to_string());
+        }
+
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_navdata() -> NavData {
+        NavData::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/navdata"
+        ))
+        .unwrap()
+    }
+
+    fn get_idents(parsed: &ParsedRoute) -> Vec<&str> {
+        parsed.fixes.iter().map(|x| x.fix.ident.as_str()).collect()
+    }
+
+    const BOSTON: LatLon = LatLon {
+        lat: 42.36,
+        lon: -71.0,
+    };
+
+    #[test]
+    fn expands_airways() {
+        let navdata = get_navdata();
+        let parsed = navdata.parse_route("N0450F350 AAA J1 DDD EEE", "KBOS", "KBGR", BOSTON);
+
+        assert_eq!(get_idents(&parsed), vec!["AAA", "BBB", "CCC", "DDD", "EEE"]);
+        assert_eq!(parsed.fixes[0].via, None);
+        assert_eq!(parsed.fixes[2].via.as_deref(), Some("J1"));
+        assert_eq!(parsed.fixes[3].via.as_deref(), Some("J1"));
+        assert_eq!(parsed.fixes[4].fix.kind, FixKind::Vor);
+        // The closer of the two fixes named BBB
+        assert_eq!(parsed.fixes[1].fix.region, "K6");
+        assert!(parsed.unknown.is_empty());
+    }
+
+    #[test]
+    fn expands_shared_and_reversed_segments() {
+        let navdata = get_navdata();
+
+        let parsed = navdata.parse_route("BBB Q2 CCC", "", "", BOSTON);
+        assert_eq!(get_idents(&parsed), vec!["BBB", "CCC"]);
+        assert_eq!(parsed.fixes[1].via.as_deref(), Some("Q2"));
+
+        let parsed = navdata.parse_route("DDD J1 AAA", "", "", BOSTON);
+        assert_eq!(get_idents(&parsed), vec!["DDD", "CCC", "BBB", "AAA"]);
+    }
+
+    #[test]
+    fn keeps_one_way_airways() {
+        let navdata = get_navdata();
+
+        let parsed = navdata.parse_route("CCC Q1 EEE", "", "", BOSTON);
+        assert_eq!(get_idents(&parsed), vec!["CCC", "EEE"]);
+        assert_eq!(parsed.fixes[1].via.as_deref(), Some("Q1"));
+
+        // Q1 can't be flown backwards, so it is left unmatched
+        let parsed = navdata.parse_route("EEE Q1 CCC", "", "", BOSTON);
+        assert_eq!(get_idents(&parsed), vec!["EEE", "CCC"]);
+        assert_eq!(parsed.unknown, vec!["Q1"]);
+    }
+
+    #[test]
+    fn does_not_repeat_exit_at_entry() {
+        let navdata = get_navdata();
+        let parsed = navdata.parse_route("AAA J1 AAA BBB", "", "", BOSTON);
+
+        assert_eq!(get_idents(&parsed), vec!["AAA", "BBB"]);
+        assert!(parsed.unknown.is_empty());
+    }
+
+    #[test]
+    fn detects_procedures() {
+        let navdata = get_navdata();
+        let parsed = navdata.parse_route(
+            "KBOS SSOXS5 AAA/N0450F370 J1 DDD 4400N06900W ROBUC3 KBGR",
+            "KBOS",
+            "KBGR",
+            BOSTON,
+        );
+
+        assert_eq!(parsed.sid.as_deref(), Some("SSOXS5"));
+        assert_eq!(parsed.star.as_deref(), Some("ROBUC3"));
+        assert_eq!(
+            get_idents(&parsed),
+            vec!["AAA", "BBB", "CCC", "DDD", "4400N06900W"]
+        );
+        assert_eq!(parsed.fixes[4].fix.kind, FixKind::Coordinate);
+        assert!(parsed.unknown.is_empty());
+
+        // Without a fix before it, a single procedure is taken as the departure
+        let parsed = navdata.parse_route("ROBUC3", "", "", BOSTON);
+        assert_eq!(parsed.sid.as_deref(), Some("ROBUC3"));
+        assert_eq!(parsed.star, None);
+    }
+}
@@ -1,54 +1,108 @@
 use attohttpc;
-use csv;
+use serde::Deserialize;
 
 use crate::error::Error;
-use crate::request::Request;
+use crate::metar::Metar;
+use crate::taf::Taf;
+use crate::weather::WeatherSource;
 
-const METAR_ENDPOINT: &str = "https://www.aviationweather.gov/adds/dataserver_current/httpparam?dataSource=metars&requestType=retrieve&format=csv&hoursBeforeNow=2&mostRecent=true&stationString=";
+const METAR_ENDPOINT: &str = "https://aviationweather.gov/api/data/metar";
+const TAF_ENDPOINT: &str = "https://aviationweather.gov/api/data/taf";
 
-pub struct NoaaWeather {
-    weather_request: Request<Result<String, Error>, String>,
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetarResponse {
+    raw_ob: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TafResponse {
+    #[serde(rename = "rawTAF")]
+    raw_taf: String,
+}
+
+/// Current reports from the Aviation Weather Center data API
+pub struct NoaaWeather {}
+
 impl NoaaWeather {
     pub fn new() -> Self {
-        Self {
-            weather_request: Request::new(1),
-        }
-    }
-
-    pub fn run(&self) {
-        self.weather_request.run(move |icao| {
-            // Get data from NOAA
-            let text = attohttpc::get(METAR_ENDPOINT.to_owned() + &icao)
-                .send()?
-                .error_for_status()?
-                .text()?;
-            // Ignore first lines of metadata
-            let text = text
-                .split("\n")
-                .skip(5)
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join("\n");
-
-            // Parse csv
-            let mut reader = csv::Reader::from_reader(text.as_bytes());
-            for record in reader.records() {
-                match record {
-                    Ok(record) => return Ok(record[0].to_string()),
-                    _ => (),
-                }
-            }
-            return Err(Error::NotFound);
-        });
-    }
-
-    pub fn request_weather(&self, icao: &str) {
-        self.weather_request.give_job(icao.to_string());
-    }
-
-    pub fn get_next_weather(&self) -> Option<Result<String, Error>> {
-        return self.weather_request.get_next();
+        Self {}
+    }
+
+    fn fetch<T>(&self, endpoint: &str, stations: &[String]) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let text = attohttpc::get(endpoint)
+            .param("ids", stations.join(","))
+            .param("format", "json")
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        parse_response(&text)
+    }
+}
+
+fn parse_response<T>(text: &str) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    // Nothing is returned when none of the stations have a report
+    if text.trim() == "" {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(text)?)
+}
+
+impl WeatherSource for NoaaWeather {
+    fn get_metars(&self, stations: &[String]) -> Result<Vec<Metar>, Error> {
+        Ok(self
+            .fetch::<MetarResponse>(METAR_ENDPOINT, stations)?
+            .into_iter()
+            .map(|x| Metar::parse(&x.raw_ob))
+            .collect())
+    }
+
+    fn get_tafs(&self, stations: &[String]) -> Result<Vec<Taf>, Error> {
+        Ok(self
+            .fetch::<TafResponse>(TAF_ENDPOINT, stations)?
+            .into_iter()
+            .map(|x| Taf::parse(&x.raw_taf))
+            .collect())
+    }
+
+    fn get_name(&self) -> &str {
+        "NOAA"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_station_in_batch() {
+        let text = r#"[
+            {"icaoId": "KBOS", "rawOb": "KBOS 181554Z 27012KT 10SM FEW050 18/06 A3002"},
+            {"icaoId": "KJFK", "rawOb": "KJFK 181551Z 30010KT 10SM SCT250 19/07 A3004"},
+            {"icaoId": "KPVD", "rawOb": "KPVD 181551Z 28008KT 10SM CLR 17/05 A3003"}
+        ]"#;
+
+        let metars: Vec<Metar> = parse_response::<MetarResponse>(text)
+            .unwrap()
+            .into_iter()
+            .map(|x| Metar::parse(&x.raw_ob))
+            .collect();
+
+        let stations: Vec<&str> = metars.iter().map(|x| x.station.as_str()).collect();
+        assert_eq!(stations, vec!["KBOS", "KJFK", "KPVD"]);
+    }
+
+    #[test]
+    fn parses_empty_response() {
+        assert!(parse_response::<TafResponse>(" \n").unwrap().is_empty());
     }
 }
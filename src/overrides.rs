@@ -0,0 +1,194 @@
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::routefile::read_records;
+
+// How often the file is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Fields set here replace the ones from any flight plan source
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct FlightPlanOverride {
+    #[serde(default)]
+    pub callsign: Option<String>,
+    #[serde(default)]
+    pub registration: Option<String>,
+    #[serde(default)]
+    pub flight_rules: Option<String>,
+    #[serde(default)]
+    pub equipment: Option<String>,
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub destination: Option<String>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub speed: Option<u64>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub altitude: Option<u64>,
+    #[serde(default)]
+    pub route: Option<String>,
+    #[serde(default)]
+    pub alternate: Option<String>,
+    #[serde(default)]
+    pub remarks: Option<String>,
+}
+
+impl FlightPlanOverride {
+    // Empty CSV cells and JSON strings count as not set. Colons and line breaks would split
+    // the colon delimited FSD packets the values are sent in, so they are replaced with spaces.
+    fn normalize(mut self) -> Self {
+        for field in [
+            &mut self.callsign,
+            &mut self.registration,
+            &mut self.flight_rules,
+            &mut self.equipment,
+            &mut self.origin,
+            &mut self.destination,
+            &mut self.route,
+            &mut self.alternate,
+            &mut self.remarks,
+        ] {
+            *field = field
+                .take()
+                .map(|x| {
+                    x.replace(|c: char| c == ':' || c.is_control(), " ")
+                        .trim()
+                        .to_string()
+                })
+                .filter(|x| x != "");
+        }
+
+        // Flight levels are accepted as well as feet
+        self.altitude = self.altitude.map(|x| if x < 1000 { x * 100 } else { x });
+
+        self
+    }
+}
+
+/// User maintained flight plans keyed by callsign or registration, reloaded when the file changes
+pub struct FlightPlanOverrides {
+    filename: String,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    by_callsign: HashMap<String, FlightPlanOverride>,
+    by_registration: HashMap<String, FlightPlanOverride>,
+}
+
+impl FlightPlanOverrides {
+    pub fn new(filename: &str) -> Self {
+        let mut overrides = Self {
+            filename: filename.to_string(),
+            modified: None,
+            last_check: Instant::now(),
+            by_callsign: HashMap::new(),
+            by_registration: HashMap::new(),
+        };
+
+        overrides.reload();
+
+        overrides
+    }
+
+    fn get_modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.filename).and_then(|x| x.modified()).ok()
+    }
+
+    fn reload(&mut self) {
+        self.modified = self.get_modified();
+
+        let records = match read_records::<FlightPlanOverride>(&self.filename) {
+            Ok(r) => r,
+            Err(e) => {
+                // Keep the last good overrides while the file is being edited
+                warn!(
+                    "Could not read flight plan overrides from {}! Reason: {:?}",
+                    self.filename, e
+                );
+                return;
+            }
+        };
+
+        self.by_callsign.clear();
+        self.by_registration.clear();
+
+        for record in records.into_iter().map(FlightPlanOverride::normalize) {
+            if let Some(callsign) = &record.callsign {
+                self.by_callsign
+                    .insert(callsign.to_uppercase(), record.clone());
+            }
+            if let Some(registration) = &record.registration {
+                self.by_registration
+                    .insert(registration.to_uppercase(), record.clone());
+            }
+        }
+
+        info!(
+            "Loaded {} flight plan overrides from {}",
+            self.by_callsign.len() + self.by_registration.len(),
+            self.filename
+        );
+    }
+
+    pub fn reload_if_changed(&mut self) {
+        if self.last_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        if self.get_modified() != self.modified {
+            self.reload();
+        }
+    }
+
+    /// Callsign matches take precedence over registration matches
+    pub fn get(&self, callsign: &str, registration: &str) -> Option<&FlightPlanOverride> {
+        self.by_callsign
+            .get(&callsign.to_uppercase())
+            .or_else(|| self.by_registration.get(&registration.to_uppercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_packet_delimiters() {
+        let record = FlightPlanOverride {
+            callsign: Some("N123AB".to_string()),
+            route: Some("BOS:J1\r\nSSOXS5".to_string()),
+            remarks: Some("TRAINING: CALL 1\tTWR\n".to_string()),
+            alternate: Some(" : ".to_string()),
+            altitude: Some(350),
+            ..Default::default()
+        }
+        .normalize();
+
+        assert_eq!(record.callsign.as_deref(), Some("N123AB"));
+        assert_eq!(record.route.as_deref(), Some("BOS J1  SSOXS5"));
+        assert_eq!(record.remarks.as_deref(), Some("TRAINING  CALL 1 TWR"));
+        // Nothing left once cleaned up
+        assert_eq!(record.alternate, None);
+        assert_eq!(record.altitude, Some(35000));
+    }
+
+    #[test]
+    fn loads_cleaned_overrides() {
+        let path = std::env::temp_dir().join(format!("overrides_{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"[{"registration": "n123ab", "route": "DCT:BOS", "remarks": "LINE\r\nBREAK"}]"#,
+        )
+        .unwrap();
+
+        let overrides = FlightPlanOverrides::new(&path.to_string_lossy());
+        let record = overrides.get("", "N123AB").unwrap();
+        assert_eq!(record.route.as_deref(), Some("DCT BOS"));
+        assert_eq!(record.remarks.as_deref(), Some("LINE  BREAK"));
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -54,3 +54,9 @@ impl Providers {
         self.request.get_next()
     }
 }
+
+impl Drop for Providers {
+    fn drop(&mut self) {
+        self.request.stop()
+    }
+}
@@ -1,18 +1,35 @@
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering::SeqCst},
-        Arc,
+        Arc, Mutex,
     },
     thread,
 };
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use crossbeam_deque::{Steal, Worker};
+
+// Jobs given without a priority run after all prioritized jobs, in order
+const DEFAULT_PRIORITY: u32 = u32::MAX;
+
+struct QueuedJob<J> {
+    key: Option<String>,
+    priority: u32,
+    job: J,
+}
+
+type Queue<J> = Arc<Mutex<Vec<QueuedJob<J>>>>;
+
+fn take_next_job<J>(queue: &Queue<J>) -> Option<J> {
+    let mut queue = queue.lock().unwrap();
+    // Lowest priority value first, the oldest job wins ties
+    let (index, _) = queue.iter().enumerate().min_by_key(|(_, x)| x.priority)?;
+    Some(queue.remove(index).job)
+}
 
 pub struct Request<T, J> {
     tx: Sender<T>,
     rx: Receiver<T>,
-    worker: Worker<J>,
+    queue: Queue<J>,
     num_threads: u32,
     running: Arc<AtomicBool>,
 }
@@ -27,7 +44,7 @@ where
         Self {
             rx,
             tx,
-            worker: Worker::new_fifo(),
+            queue: Arc::new(Mutex::new(Vec::new())),
             num_threads,
             running: Arc::new(AtomicBool::new(false)),
         }
@@ -43,7 +60,7 @@ where
 
         // Spawn worker threads to read from queue
         (0..self.num_threads).for_each(|_| {
-            let s = self.worker.stealer();
+            let queue = self.queue.clone();
             let result_transmitter = self.tx.clone();
             let worker = worker.clone();
 
@@ -51,11 +68,8 @@ where
 
             // Process tasks
             thread::spawn(move || loop {
-                match s.steal() {
-                    Steal::Success(job) => {
-                        result_transmitter.send(worker(job)).ok();
-                    }
-                    _ => (),
+                if let Some(job) = take_next_job(&queue) {
+                    result_transmitter.send(worker(job)).ok();
                 }
 
                 if running_clone.load(SeqCst) {
@@ -79,6 +93,97 @@ where
     }
 
     pub fn give_job(&self, job: J) {
-        self.worker.push(job);
+        self.queue.lock().unwrap().push(QueuedJob {
+            key: None,
+            priority: DEFAULT_PRIORITY,
+            job,
+        });
+    }
+
+    /// Queues a job that runs before jobs with a higher priority value.
+    /// Only one job is kept per key, a repeated key replaces the queued job and can only raise its priority.
+    pub fn give_job_with_priority(&self, key: &str, priority: u32, job: J) {
+        let mut queue = self.queue.lock().unwrap();
+
+        if let Some(queued) = queue.iter_mut().find(|x| x.key.as_deref() == Some(key)) {
+            queued.priority = queued.priority.min(priority);
+            queued.job = job;
+            return;
+        }
+
+        queue.push(QueuedJob {
+            key: Some(key.to_string()),
+            priority,
+            job,
+        });
+    }
+
+    /// Removes queued jobs whose key does not pass `should_keep`
+    pub fn cancel_jobs<F>(&self, should_keep: F)
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.queue
+            .lock()
+            .unwrap()
+            .retain(|x| x.key.as_deref().map(&should_keep).unwrap_or(true));
+    }
+
+    pub fn has_job(&self, key: &str) -> bool {
+        self.queue
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|x| x.key.as_deref() == Some(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_all(request: &Request<(), &'static str>) -> Vec<&'static str> {
+        std::iter::from_fn(|| take_next_job(&request.queue)).collect()
+    }
+
+    #[test]
+    fn runs_lowest_priority_first() {
+        let request = Request::<(), &str>::new(1);
+        request.give_job("unkeyed");
+        request.give_job_with_priority("far", 30, "far");
+        request.give_job_with_priority("near", 2, "near");
+        request.give_job_with_priority("also near", 2, "also near");
+
+        assert_eq!(
+            take_all(&request),
+            vec!["near", "also near", "far", "unkeyed"]
+        );
+    }
+
+    #[test]
+    fn replaces_queued_job_with_same_key() {
+        let request = Request::<(), &str>::new(1);
+        request.give_job_with_priority("a", 10, "old");
+        request.give_job_with_priority("b", 5, "b");
+        request.give_job_with_priority("a", 1, "new");
+        // A later, less urgent request doesn't lower the priority again
+        request.give_job_with_priority("a", 20, "newest");
+
+        assert_eq!(take_all(&request), vec!["newest", "b"]);
+    }
+
+    #[test]
+    fn cancels_and_finds_jobs_by_key() {
+        let request = Request::<(), &str>::new(1);
+        request.give_job("unkeyed");
+        request.give_job_with_priority("keep", 1, "keep");
+        request.give_job_with_priority("drop", 1, "drop");
+
+        assert!(request.has_job("drop"));
+        request.cancel_jobs(|key| key != "drop");
+        assert!(!request.has_job("drop"));
+        assert!(request.has_job("keep"));
+
+        assert_eq!(take_all(&request), vec!["keep", "unkeyed"]);
     }
 }
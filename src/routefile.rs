@@ -0,0 +1,129 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::flightplan::{Aircraft, Airport, FlightPlan, FlightPlanProvider, PartialFlightPlan};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RouteRecord {
+    pub callsign: String,
+    pub origin: String,
+    #[serde(default)]
+    pub destination: String,
+    #[serde(default)]
+    pub equipment: String,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub speed: Option<u64>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub altitude: Option<u64>,
+    #[serde(default)]
+    pub route: String,
+    #[serde(default)]
+    pub alternate: String,
+    // Minutes
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub fuel: Option<u32>,
+}
+
+impl RouteRecord {
+    fn to_flightplan(&self) -> FlightPlan {
+        let altitude = self.altitude.unwrap_or_default();
+
+        FlightPlan {
+            origin: Airport {
+                icao: self.origin.clone(),
+                ..Default::default()
+            },
+            destination: Airport {
+                icao: self.destination.clone(),
+                ..Default::default()
+            },
+            equipment: Aircraft {
+                ac_type: self.equipment.clone(),
+            },
+            fp: PartialFlightPlan {
+                speed: self.speed.unwrap_or_default(),
+                // Flight levels are accepted as well as feet
+                altitude: if altitude < 1000 {
+                    altitude * 100
+                } else {
+                    altitude
+                },
+                route: self.route.clone(),
+            },
+            arrival_time: None,
+            departure_time: None,
+            alternate: Some(self.alternate.clone()).filter(|x| x != ""),
+            fuel_minutes: self.fuel,
+        }
+    }
+}
+
+/// Reads records from a CSV file with a header row, or a JSON array of objects
+pub fn read_records<T>(filename: &str) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let is_json = Path::new(filename)
+        .extension()
+        .map(|x| x.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    if is_json {
+        return Ok(serde_json::from_reader(File::open(filename)?)?);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(filename)?;
+
+    let mut records = Vec::new();
+    for record in reader.deserialize() {
+        records.push(record?);
+    }
+
+    Ok(records)
+}
+
+/// Flight plans from a user maintained schedule, keyed by callsign
+pub struct RouteFile {
+    name: String,
+    routes: HashMap<String, RouteRecord>,
+}
+
+impl RouteFile {
+    pub fn new(filename: &str) -> Result<Self, Error> {
+        let routes = read_records::<RouteRecord>(filename)?
+            .into_iter()
+            .map(|x| (x.callsign.to_uppercase(), x))
+            .collect();
+
+        Ok(Self {
+            name: filename.to_string(),
+            routes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+}
+
+impl FlightPlanProvider for RouteFile {
+    fn get_flightplan(&self, callsign: &str) -> Result<FlightPlan, Error> {
+        self.routes
+            .get(&callsign.to_uppercase())
+            .map(|x| x.to_flightplan())
+            .ok_or(Error::NotFound)
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn can_provide(&self, callsign: &str) -> bool {
+        self.routes.contains_key(&callsign.to_uppercase())
+    }
+}
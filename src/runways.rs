@@ -0,0 +1,362 @@
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::util::{heading_difference, AircraftData, LatLon};
+
+// Runway use older than this no longer counts
+const ACTIVE_RUNWAY_WINDOW: Duration = Duration::from_secs(20 * 60);
+// Runways with fewer movements than this share of the busiest one are ignored
+const MIN_ACTIVE_SHARE: f32 = 0.25;
+// How far off the track can be from the runway heading, degrees
+const MAX_TRACK_DIFFERENCE: f32 = 15.0;
+// How far off the extended centerline an aircraft can be, nm
+const MAX_CENTERLINE_OFFSET: f32 = 0.5;
+// How far out on final arrivals are picked up, nm
+const MAX_FINAL_DISTANCE: f32 = 6.0;
+// How far past the runway end departures are picked up, nm
+const MAX_DEPARTURE_DISTANCE: f32 = 3.0;
+// Height above the field, feet
+const MAX_ARRIVAL_HEIGHT: i32 = 3000;
+const MAX_DEPARTURE_HEIGHT: i32 = 2500;
+// Feet per minute needed to tell a climb from a descent
+const MIN_VERTICAL_RATE: i32 = 200;
+const FEET_PER_NM: f32 = 6076.0;
+
+/// A row of OurAirports' runways.csv, with both ends of the runway
+#[derive(Deserialize, Debug)]
+struct RunwayRecord {
+    airport_ident: String,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    length_ft: Option<u32>,
+    #[serde(default)]
+    closed: u8,
+    le_ident: String,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    le_latitude_deg: Option<f32>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    le_longitude_deg: Option<f32>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    le_elevation_ft: Option<i32>,
+    #[serde(
+        default,
+        rename = "le_heading_degT",
+        deserialize_with = "csv::invalid_option"
+    )]
+    le_heading: Option<f32>,
+    he_ident: String,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    he_latitude_deg: Option<f32>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    he_longitude_deg: Option<f32>,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    he_elevation_ft: Option<i32>,
+    #[serde(
+        default,
+        rename = "he_heading_degT",
+        deserialize_with = "csv::invalid_option"
+    )]
+    he_heading: Option<f32>,
+}
+
+/// One direction of a runway, starting at its threshold
+#[derive(Debug, Clone)]
+pub struct RunwayEnd {
+    pub ident: String,
+    pub threshold: LatLon,
+    // True heading in degrees
+    pub heading: f32,
+    pub length_ft: Option<u32>,
+    pub elevation_ft: Option<i32>,
+}
+
+impl RunwayEnd {
+    // Distance along the runway heading from the threshold and off the centerline, in nm
+    fn get_offset(&self, position: &LatLon) -> (f32, f32) {
+        let distance = self.threshold.distance_to(position);
+        let angle = (self.threshold.bearing_to(position) - self.heading).to_radians();
+
+        (distance * angle.cos(), distance * angle.sin())
+    }
+
+    fn get_length_nm(&self) -> f32 {
+        self.length_ft.unwrap_or_default() as f32 / FEET_PER_NM
+    }
+}
+
+fn read_runway_ends(record: RunwayRecord) -> Option<[RunwayEnd; 2]> {
+    let le_threshold = LatLon {
+        lat: record.le_latitude_deg?,
+        lon: record.le_longitude_deg?,
+    };
+    let he_threshold = LatLon {
+        lat: record.he_latitude_deg?,
+        lon: record.he_longitude_deg?,
+    };
+
+    // Headings are often missing for smaller fields
+    let le_heading = record
+        .le_heading
+        .unwrap_or_else(|| le_threshold.bearing_to(&he_threshold));
+    let he_heading = record
+        .he_heading
+        .unwrap_or_else(|| he_threshold.bearing_to(&le_threshold));
+
+    Some([
+        RunwayEnd {
+            ident: record.le_ident,
+            threshold: le_threshold,
+            heading: le_heading,
+            length_ft: record.length_ft,
+            elevation_ft: record.le_elevation_ft,
+        },
+        RunwayEnd {
+            ident: record.he_ident,
+            threshold: he_threshold,
+            heading: he_heading,
+            length_ft: record.length_ft,
+            elevation_ft: record.he_elevation_ft,
+        },
+    ])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunwayUse {
+    Arrival,
+    Departure,
+}
+
+struct RunwayEvent {
+    hex: String,
+    runway: String,
+    kind: RunwayUse,
+    time: Instant,
+}
+
+/// Runways of the configured airport, and which of them aircraft are using
+pub struct Runways {
+    ends: Vec<RunwayEnd>,
+    field_elevation: i32,
+    events: VecDeque<RunwayEvent>,
+}
+
+impl Runways {
+    pub fn new(
+        filename: &str,
+        airport: &str,
+        field_elevation: Option<i32>,
+    ) -> Result<Self, csv::Error> {
+        let mut reader = csv::Reader::from_path(filename)?;
+        let mut ends = Vec::new();
+
+        for record in reader.deserialize() {
+            let record: RunwayRecord = record?;
+            if record.airport_ident != airport || record.closed != 0 {
+                continue;
+            }
+
+            if let Some(runway) = read_runway_ends(record) {
+                ends.extend(runway);
+            }
+        }
+
+        // Without a field elevation, the highest threshold is close enough
+        let field_elevation = field_elevation
+            .or_else(|| ends.iter().filter_map(|x| x.elevation_ft).max())
+            .unwrap_or_default();
+
+        Ok(Self::from_ends(ends, field_elevation))
+    }
+
+    pub fn from_ends(ends: Vec<RunwayEnd>, field_elevation: i32) -> Self {
+        Self {
+            ends,
+            field_elevation,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn get_ends(&self) -> &Vec<RunwayEnd> {
+        &self.ends
+    }
+
+    /// Finds the runway the aircraft is landing on or departing from, if any
+    pub fn classify(&self, ac_data: &AircraftData) -> Option<(&RunwayEnd, RunwayUse)> {
+        if ac_data.is_on_ground {
+            return None;
+        }
+
+        let height = ac_data.altitude - self.field_elevation;
+        let kind = if ac_data.vertical_rate <= -MIN_VERTICAL_RATE && height <= MAX_ARRIVAL_HEIGHT {
+            RunwayUse::Arrival
+        } else if ac_data.vertical_rate >= MIN_VERTICAL_RATE && height <= MAX_DEPARTURE_HEIGHT {
+            RunwayUse::Departure
+        } else {
+            return None;
+        };
+
+        let position = ac_data.get_position();
+
+        self.ends
+            .iter()
+            .filter(|x| {
+                heading_difference(x.heading, ac_data.heading as f32) <= MAX_TRACK_DIFFERENCE
+            })
+            .filter_map(|x| {
+                let (along, across) = x.get_offset(&position);
+                let is_in_area = match kind {
+                    // Short of the threshold, or just over it in the flare
+                    RunwayUse::Arrival => along >= -MAX_FINAL_DISTANCE && along <= 0.5,
+                    RunwayUse::Departure => {
+                        along >= 0.0 && along <= x.get_length_nm() + MAX_DEPARTURE_DISTANCE
+                    }
+                };
+
+                if is_in_area && across.abs() <= MAX_CENTERLINE_OFFSET {
+                    Some((x, across.abs()))
+                } else {
+                    None
+                }
+            })
+            // Closest centerline for parallel runways
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(x, _)| (x, kind))
+    }
+
+    /// Records the aircraft's runway use, returns whether it was new
+    pub fn update(&mut self, ac_data: &AircraftData) -> bool {
+        while let Some(event) = self.events.front() {
+            if event.time.elapsed() < ACTIVE_RUNWAY_WINDOW {
+                break;
+            }
+            self.events.pop_front();
+        }
+
+        let (runway, kind) = match self.classify(ac_data) {
+            Some((runway, kind)) => (runway.ident.clone(), kind),
+            None => return false,
+        };
+
+        // Each arrival or departure is counted once
+        if self
+            .events
+            .iter()
+            .any(|x| x.hex == ac_data.hex && x.kind == kind)
+        {
+            return false;
+        }
+
+        self.events.push_back(RunwayEvent {
+            hex: ac_data.hex.clone(),
+            runway,
+            kind,
+            time: Instant::now(),
+        });
+
+        true
+    }
+
+    /// Runways in use for arrivals or departures, busiest first
+    pub fn get_active(&self, kind: RunwayUse) -> Vec<String> {
+        let mut counts: HashMap<&String, u32> = HashMap::new();
+        for event in self.events.iter().filter(|x| x.kind == kind) {
+            *counts.entry(&event.runway).or_default() += 1;
+        }
+
+        let busiest = counts.values().copied().max().unwrap_or_default();
+        let mut active: Vec<(&String, u32)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count as f32 >= busiest as f32 * MIN_ACTIVE_SHARE)
+            .collect();
+
+        active.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        active.into_iter().map(|(x, _)| x.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parallel runways, roughly like 4L/4R at KBOS
+    fn get_runways() -> Runways {
+        let runway = |ident: &str, lat: f32, lon: f32, heading: f32| RunwayEnd {
+            ident: ident.to_string(),
+            threshold: LatLon { lat, lon },
+            heading,
+            length_ft: Some(8000),
+            elevation_ft: Some(20),
+        };
+
+        Runways::from_ends(
+            vec![
+                runway("4L", 42.3550, -71.0150, 35.0),
+                runway("22R", 42.3730, -71.0000, 215.0),
+                runway("4R", 42.3510, -71.0110, 35.0),
+                runway("22L", 42.3690, -70.9960, 215.0),
+            ],
+            20,
+        )
+    }
+
+    fn get_aircraft(
+        hex: &str,
+        lat: f32,
+        lon: f32,
+        heading: u32,
+        altitude: i32,
+        vertical_rate: i32,
+    ) -> AircraftData {
+        AircraftData {
+            hex: hex.to_string(),
+            latitude: lat,
+            longitude: lon,
+            heading,
+            altitude,
+            vertical_rate,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn classifies_arrivals_and_departures() {
+        let runways = get_runways();
+
+        // 3nm final for 4R
+        let arrival = get_aircraft("a", 42.3155, -71.0440, 35, 1000, -700);
+        let (runway, kind) = runways.classify(&arrival).unwrap();
+        assert_eq!(runway.ident, "4R");
+        assert_eq!(kind, RunwayUse::Arrival);
+
+        // Climbing out past the far end of 4L
+        let departure = get_aircraft("b", 42.3800, -70.9930, 35, 1500, 2000);
+        let (runway, kind) = runways.classify(&departure).unwrap();
+        assert_eq!(runway.ident, "4L");
+        assert_eq!(kind, RunwayUse::Departure);
+
+        // Level overflight
+        assert!(runways
+            .classify(&get_aircraft("c", 42.3155, -71.0440, 35, 1000, 0))
+            .is_none());
+
+        // Wrong direction for the runway it is lined up with
+        assert!(runways
+            .classify(&get_aircraft("d", 42.3155, -71.0440, 215, 1000, -700))
+            .is_none());
+    }
+
+    #[test]
+    fn finds_active_runways() {
+        let mut runways = get_runways();
+
+        assert!(runways.update(&get_aircraft("a", 42.3155, -71.0440, 35, 1000, -700)));
+        // The same arrival seen again is not counted twice
+        assert!(!runways.update(&get_aircraft("a", 42.3300, -71.0290, 35, 600, -700)));
+        assert!(runways.update(&get_aircraft("b", 42.3155, -71.0440, 35, 1000, -700)));
+        assert!(runways.update(&get_aircraft("c", 42.3800, -70.9930, 35, 1500, 2000)));
+
+        assert_eq!(runways.get_active(RunwayUse::Arrival), vec!["4R"]);
+        assert_eq!(runways.get_active(RunwayUse::Departure), vec!["4L"]);
+    }
+}
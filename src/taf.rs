@@ -0,0 +1,236 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::metar::Metar;
+
+lazy_static! {
+    static ref ISSUED_REGEX: Regex = Regex::new(r"^(\d{2})(\d{2})(\d{2})Z$").unwrap();
+    // DDHH/DDHH
+    static ref VALIDITY_REGEX: Regex = Regex::new(r"^(\d{2})(\d{2})/(\d{2})(\d{2})$").unwrap();
+    // FMDDHHMM
+    static ref FROM_REGEX: Regex = Regex::new(r"^FM(\d{2})(\d{2})(\d{2})$").unwrap();
+    static ref PROBABILITY_REGEX: Regex = Regex::new(r"^PROB(\d{2})$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    // Conditions at the start of the forecast
+    Base,
+    From,
+    Becoming,
+    Temporary,
+    // Only a probability, without TEMPO
+    Probability,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForecastPeriod {
+    pub kind: ChangeKind,
+    pub probability: Option<u32>,
+    // Day of month and hour in UTC
+    pub valid_from: Option<(u32, u32)>,
+    pub valid_to: Option<(u32, u32)>,
+    // Groups of the period as they appear in the TAF
+    pub text: String,
+    pub conditions: Metar,
+}
+
+impl ForecastPeriod {
+    fn new(kind: ChangeKind) -> Self {
+        Self {
+            kind,
+            probability: None,
+            valid_from: None,
+            valid_to: None,
+            text: String::new(),
+            conditions: Metar::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Taf {
+    pub station: String,
+    pub raw: String,
+    // Day of month, hour and minute in UTC
+    pub issued: Option<(u32, u32, u32)>,
+    pub periods: Vec<ForecastPeriod>,
+}
+
+fn finish_period(periods: &mut Vec<ForecastPeriod>, mut period: ForecastPeriod, tokens: &[&str]) {
+    period.text = tokens.join(" ");
+    period.conditions.parse_groups(tokens);
+    periods.push(period);
+}
+
+impl Taf {
+    pub fn parse(raw: &str) -> Self {
+        let mut taf = Taf {
+            raw: raw.trim().to_string(),
+            ..Default::default()
+        };
+
+        let tokens: Vec<&str> = raw
+            .split_whitespace()
+            .skip_while(|x| matches!(*x, "TAF" | "AMD" | "COR"))
+            .take_while(|x| *x != "RMK")
+            .collect();
+
+        let mut tokens = tokens.into_iter();
+        taf.station = tokens.next().unwrap_or_default().to_string();
+
+        let mut period = ForecastPeriod::new(ChangeKind::Base);
+        let mut groups: Vec<&str> = Vec::new();
+
+        for token in tokens {
+            let next_kind = if let Some(c) = FROM_REGEX.captures(token) {
+                let mut next = ForecastPeriod::new(ChangeKind::From);
+                next.valid_from = Some((c[1].parse().unwrap(), c[2].parse().unwrap()));
+                Some(next)
+            } else if token == "BECMG" {
+                Some(ForecastPeriod::new(ChangeKind::Becoming))
+            } else if token == "TEMPO"
+                && period.kind == ChangeKind::Probability
+                && groups.is_empty()
+            {
+                // PROB30 TEMPO is a single change
+                period.kind = ChangeKind::Temporary;
+                None
+            } else if token == "TEMPO" {
+                Some(ForecastPeriod::new(ChangeKind::Temporary))
+            } else if let Some(c) = PROBABILITY_REGEX.captures(token) {
+                let mut next = ForecastPeriod::new(ChangeKind::Probability);
+                next.probability = c[1].parse().ok();
+                Some(next)
+            } else if let Some(c) = VALIDITY_REGEX.captures(token).filter(|_| groups.is_empty()) {
+                period.valid_from = Some((c[1].parse().unwrap(), c[2].parse().unwrap()));
+                period.valid_to = Some((c[3].parse().unwrap(), c[4].parse().unwrap()));
+                None
+            } else if let Some(c) = ISSUED_REGEX
+                .captures(token)
+                .filter(|_| taf.issued.is_none())
+            {
+                taf.issued = Some((
+                    c[1].parse().unwrap(),
+                    c[2].parse().unwrap(),
+                    c[3].parse().unwrap(),
+                ));
+                None
+            } else {
+                groups.push(token);
+                None
+            };
+
+            if let Some(next) = next_kind {
+                finish_period(&mut taf.periods, period, &groups);
+                period = next;
+                groups.clear();
+            }
+        }
+
+        finish_period(&mut taf.periods, period, &groups);
+
+        taf
+    }
+
+    /// Splits the TAF into one line per forecast period
+    pub fn get_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        let mut previous = "";
+
+        for token in self.raw.split_whitespace() {
+            let starts_period = FROM_REGEX.is_match(token)
+                || token == "BECMG"
+                || PROBABILITY_REGEX.is_match(token)
+                // PROB30 TEMPO stays on one line
+                || (token == "TEMPO" && !PROBABILITY_REGEX.is_match(previous));
+
+            if starts_period && current != "" {
+                lines.push(std::mem::take(&mut current));
+            }
+
+            if current != "" {
+                current.push(' ');
+            }
+            current += token;
+            previous = token;
+        }
+
+        if current != "" {
+            lines.push(current);
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KBOS_TAF: &str = "TAF KBOS 181120Z 1812/1918 27015G25KT P6SM FEW050 \
+        FM181800 29012KT P6SM SCT060 \
+        TEMPO 1820/1824 3SM -SHRA BKN025 \
+        PROB30 1904/1908 1SM BR OVC008 \
+        BECMG 1912/1914 VRB03KT";
+
+    #[test]
+    fn parses_periods() {
+        let taf = Taf::parse(KBOS_TAF);
+
+        assert_eq!(taf.station, "KBOS");
+        assert_eq!(taf.issued, Some((18, 11, 20)));
+        assert_eq!(taf.periods.len(), 5);
+
+        let base = &taf.periods[0];
+        assert_eq!(base.kind, ChangeKind::Base);
+        assert_eq!(base.valid_from, Some((18, 12)));
+        assert_eq!(base.valid_to, Some((19, 18)));
+        assert_eq!(base.conditions.wind.as_ref().unwrap().gust, Some(25));
+        assert_eq!(base.text, "27015G25KT P6SM FEW050");
+
+        let from = &taf.periods[1];
+        assert_eq!(from.kind, ChangeKind::From);
+        assert_eq!(from.valid_from, Some((18, 18)));
+
+        let tempo = &taf.periods[2];
+        assert_eq!(tempo.kind, ChangeKind::Temporary);
+        assert_eq!(tempo.valid_to, Some((18, 24)));
+        assert_eq!(tempo.conditions.weather, vec!["-SHRA"]);
+        assert_eq!(tempo.conditions.get_ceiling(), Some(2500));
+
+        let prob = &taf.periods[3];
+        assert_eq!(prob.kind, ChangeKind::Probability);
+        assert_eq!(prob.probability, Some(30));
+        assert_eq!(prob.conditions.get_ceiling(), Some(800));
+
+        assert_eq!(taf.periods[4].kind, ChangeKind::Becoming);
+    }
+
+    #[test]
+    fn joins_probability_with_tempo() {
+        let taf = Taf::parse(
+            "TAF EGLL 181100Z 1812/1918 24010KT 9999 SCT040 PROB40 TEMPO 1815/1818 4000 TSRA",
+        );
+
+        assert_eq!(taf.periods.len(), 2);
+        assert_eq!(taf.periods[1].kind, ChangeKind::Temporary);
+        assert_eq!(taf.periods[1].probability, Some(40));
+        assert_eq!(taf.periods[1].conditions.weather, vec!["TSRA"]);
+    }
+
+    #[test]
+    fn splits_lines() {
+        let taf = Taf::parse("TAF EGLL 181100Z 1812/1918 24010KT 9999 SCT040 PROB40 TEMPO 1815/1818 4000 TSRA BECMG 1900/1902 20005KT");
+
+        assert_eq!(
+            taf.get_lines(),
+            vec![
+                "TAF EGLL 181100Z 1812/1918 24010KT 9999 SCT040",
+                "PROB40 TEMPO 1815/1818 4000 TSRA",
+                "BECMG 1900/1902 20005KT",
+            ]
+        );
+    }
+}
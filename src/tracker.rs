@@ -1,21 +1,71 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use log::{info, warn};
 
-use crate::flightaware::{FlightAware, FlightPlan};
-use crate::flightradar::FlightRadar;
+use crate::airports::Airports;
+use crate::error::Error;
+use crate::external::{ExternalConfig, ExternalProvider};
+use crate::flightplan::{is_plausible, FlightPlan, FlightPlanProvider, FlightPlans};
+use crate::flightradar::{FlightDetails, FlightRadar, FlightRadarDetails};
+use crate::fpcache::FlightPlanCache;
 use crate::interpolate::InterpolatePosition;
+use crate::navdata::{NavData, ParsedRoute};
 use crate::providers::Providers;
-use crate::util::AircraftMap;
-use crate::util::{is_valid_callsign, Bounds};
-use crate::{adsbexchange::AdsbExchange, util::AircraftData};
+use crate::util::{heading_difference, is_valid_callsign, Bounds, LatLon};
+use crate::util::{AircraftMap, AircraftProvider, TrackPoint};
+use crate::winds::{WindEstimate, WindsAloft};
+use crate::{
+    adsbexchange::{AdsbExchange, AdsbTrace, TraceBackfill},
+    util::AircraftData,
+};
 
 const POLL_RATE: u64 = 4;
+// Flight plan requests per route before giving up
+const MAX_FP_ATTEMPTS: u32 = 4;
+// Wait before the first retry, doubled after every failure
+const FP_RETRY_DELAY: Duration = Duration::from_secs(60);
+// Most positions kept per aircraft
+const MAX_HISTORY_LEN: usize = 2000;
+// Slower aircraft are likely taxiing or holding, so no ETA is given
+const MIN_ETA_GROUND_SPEED: u32 = 50;
+// Flight plan requests from controllers run before any automatic request
+const CONTROLLER_PRIORITY: u32 = 0;
+
+/// Whether a side of the route known when the flight plan was requested has since changed
+fn is_route_changed(origin: &str, destination: &str, requested: &(String, String)) -> bool {
+    let has_changed = |reported: &str, requested: &str| {
+        requested != "" && reported != "" && reported != requested
+    };
+
+    has_changed(origin, &requested.0) || has_changed(destination, &requested.1)
+}
+
+/// Whether the flight plan agrees with the airports the provider reports, which may be IATA codes
+fn matches_reported_route(fp: &FlightPlan, ac_data: &AircraftData, airports: &Airports) -> bool {
+    let matches = |reported: &String, icao: &str| {
+        reported == ""
+            || reported == icao
+            || airports
+                .get_icao_from_iata(reported)
+                .map(|x| x == icao)
+                .unwrap_or(false)
+    };
+
+    matches(&ac_data.origin, &fp.origin.icao) && matches(&ac_data.destination, &fp.destination.icao)
+}
 
 pub struct Tracker {
     providers: Providers,
-    faware: FlightAware,
+    flightplans: FlightPlans,
+    fp_cache: Option<FlightPlanCache>,
+    traces: AdsbTrace,
+    fr24_details: FlightRadarDetails,
+    // Keyed by FlightRadar24 flight id
+    flight_details: HashMap<String, FlightDetails>,
+    details_requested: HashSet<String>,
 
     buffer: VecDeque<AircraftMap>,
     is_buffering: bool,
@@ -23,20 +73,42 @@ pub struct Tracker {
     callsign_map: HashMap<String, String>,
     time: Option<Instant>,
 
+    airports: Arc<Airports>,
+    navdata: Option<Arc<NavData>>,
+    winds: WindsAloft,
+    // Flight plans are requested closest to here first
+    center: LatLon,
     floor: i32,
     ceiling: i32,
 }
 
 impl Tracker {
-    pub fn new(radar_loc: &Bounds, floor: i32, ceiling: i32) -> Self {
-        let providers = Providers::new(vec![
+    pub fn new(
+        radar_loc: &Bounds,
+        floor: i32,
+        ceiling: i32,
+        external_providers: &[ExternalConfig],
+        airports: Arc<Airports>,
+    ) -> Self {
+        let mut providers: Vec<Box<dyn AircraftProvider + Send + Sync>> = vec![
             Box::new(FlightRadar::new(radar_loc)),
             Box::new(AdsbExchange::new(radar_loc)),
-        ]);
+        ];
+
+        for config in external_providers {
+            providers.push(Box::new(ExternalProvider::new(config)));
+        }
+
+        let providers = Providers::new(providers);
 
         Self {
             providers,
-            faware: FlightAware::new(),
+            flightplans: FlightPlans::new(),
+            fp_cache: None,
+            traces: AdsbTrace::new(),
+            fr24_details: FlightRadarDetails::new(),
+            flight_details: HashMap::new(),
+            details_requested: HashSet::new(),
 
             buffer: VecDeque::new(),
             is_buffering: false,
@@ -44,21 +116,69 @@ impl Tracker {
             callsign_map: HashMap::new(),
             time: None,
 
+            airports,
+            navdata: None,
+            winds: WindsAloft::new(),
+            center: radar_loc.center(),
             floor,
             ceiling,
         }
     }
 
-    pub fn run_faware(&mut self) {
-        self.faware.run();
+    pub fn run_flightplans(&mut self, providers: Vec<Box<dyn FlightPlanProvider + Send + Sync>>) {
+        self.flightplans.run(providers);
+    }
+
+    pub fn use_flightplan_cache(&mut self, cache: FlightPlanCache) {
+        self.fp_cache = Some(cache);
+    }
+
+    pub fn use_navdata(&mut self, navdata: Arc<NavData>) {
+        self.navdata = Some(navdata);
+    }
+
+    pub fn run_traces(&mut self, backfill: TraceBackfill) {
+        self.traces.run(backfill);
+    }
+
+    pub fn run_fr24_details(&mut self) {
+        self.fr24_details.run();
     }
 
     pub fn run(&mut self) {
         self.providers.run();
     }
 
+    /// Fills in missing data from FlightRadar24's flight details, requesting them if needed
+    fn enrich_aircraft(&mut self, id: &String) {
+        let data = match self.tracking.get_mut(id) {
+            Some(d) => d,
+            None => return,
+        };
+
+        let fr24_id = &data.ac_data.fr24_id;
+        if fr24_id == "" {
+            return;
+        }
+
+        if let Some(details) = self.flight_details.get(fr24_id) {
+            details.apply(&mut data.ac_data);
+            return;
+        }
+
+        let is_missing_route = data.ac_data.origin == "" || data.ac_data.destination == "";
+
+        if self.fr24_details.running
+            && is_missing_route
+            && self.details_requested.insert(fr24_id.clone())
+        {
+            info!("Requesting flight details for {}", data.ac_data.callsign);
+            self.fr24_details.request_details(fr24_id);
+        }
+    }
+
     fn try_update_flightplan(&mut self, id: &String) {
-        if !self.faware.running {
+        if !self.flightplans.running {
             return;
         }
 
@@ -66,25 +186,168 @@ impl Tracker {
             Some(d) => d,
             None => return,
         };
-        // Already updated flight plan
-        if data.fp.is_some() || data.fp_did_try_update {
+
+        // The provider now reports a different route than when the plan was requested
+        let origin = &data.ac_data.origin;
+        let destination = &data.ac_data.destination;
+        let route_changed =
+            data.fp_attempts > 0 && is_route_changed(origin, destination, &data.fp_route);
+
+        // Sides that were unknown at request time are filled in, so later changes are caught
+        if data.fp_route.0 == "" {
+            data.fp_route.0 = origin.clone();
+        }
+        if data.fp_route.1 == "" {
+            data.fp_route.1 = destination.clone();
+        }
+
+        if route_changed {
+            info!(
+                "Route of {} changed to {}-{}, refreshing flight plan",
+                data.ac_data.callsign, origin, destination
+            );
+            data.fp = None;
+            data.fp_attempts = 0;
+            data.fp_retry_at = None;
+
+            // The cached plan is for the old route
+            if let Some(cache) = self.fp_cache.as_mut() {
+                cache.remove(&data.ac_data.callsign);
+            }
+        }
+
+        // Already updated flight plan or waiting on one
+        if data.fp.is_some() || data.fp_is_pending || data.fp_attempts >= MAX_FP_ATTEMPTS {
             return;
         }
-        // FP on request
-        data.fp_did_try_update = true;
 
-        // Only update airliners
-        if data.ac_data.is_airline() {
-            info!("Requesting flight plan for {}", data.ac_data.callsign);
+        // Such as general aviation callsigns, which FlightAware isn't asked about
+        if !self.flightplans.can_provide(&data.ac_data.callsign) {
+            return;
+        }
 
-            self.faware.request_flightplan(id, &data.ac_data.callsign);
+        if data
+            .fp_retry_at
+            .map(|x| Instant::now() < x)
+            .unwrap_or(false)
+        {
+            return;
         }
+
+        // Closer aircraft first, behind anything a controller asked for
+        let priority = self.center.distance_to(&data.ac_data.get_position()) as u32 + 1;
+        self.request_flightplan(id, priority);
     }
 
-    fn update_flightplan(&mut self, id: &String, fp: FlightPlan) {
-        if let Some(track_data) = self.tracking.get_mut(id) {
-            track_data.fp = Some(fp);
+    fn request_flightplan(&mut self, id: &String, priority: u32) {
+        let data = match self.tracking.get_mut(id) {
+            Some(d) => d,
+            None => return,
+        };
+
+        let origin = &data.ac_data.origin;
+        let destination = &data.ac_data.destination;
+
+        data.fp_route = (origin.clone(), destination.clone());
+        data.fp_attempts += 1;
+
+        if let Some(fp) = self
+            .fp_cache
+            .as_ref()
+            .and_then(|x| x.get(&data.ac_data.callsign))
+        {
+            if is_plausible(&fp, &data.ac_data, &self.airports)
+                && matches_reported_route(&fp, &data.ac_data, &self.airports)
+            {
+                info!("Using cached flight plan for {}", data.ac_data.callsign);
+                data.set_flightplan(fp, &self.airports, self.navdata.as_deref());
+                return;
+            }
+        }
+
+        info!("Requesting flight plan for {}", data.ac_data.callsign);
+
+        data.fp_is_pending = true;
+        self.flightplans
+            .request_flightplan(id, &data.ac_data.callsign, priority);
+    }
+
+    /// A controller asked for the flight plan, so it jumps the queue
+    pub fn prioritize_flightplan(&mut self, callsign: &String) {
+        if !self.flightplans.running {
+            return;
+        }
+
+        let id = match self.callsign_map.get(callsign) {
+            Some(id) => id.clone(),
+            None => return,
+        };
+
+        let data = match self.tracking.get(&id) {
+            Some(d) => d,
+            None => return,
+        };
+
+        if data.fp.is_some() {
+            return;
         }
+
+        if data.fp_is_pending {
+            // Raises the priority of the queued request, unless it is already being fetched
+            if self.flightplans.is_queued(callsign) {
+                self.flightplans
+                    .request_flightplan(&id, callsign, CONTROLLER_PRIORITY);
+            }
+            return;
+        }
+
+        if !self.flightplans.can_provide(callsign) {
+            return;
+        }
+
+        info!("Flight plan for {} requested by controller", callsign);
+        self.request_flightplan(&id, CONTROLLER_PRIORITY);
+    }
+
+    fn update_flightplan(&mut self, id: &String, fp: FlightPlan, should_cache: bool) {
+        let track_data = match self.tracking.get_mut(id) {
+            Some(d) => d,
+            None => return,
+        };
+
+        track_data.fp_is_pending = false;
+
+        if !is_plausible(&fp, &track_data.ac_data, &self.airports) {
+            info!(
+                "Rejected flight plan {}-{} for {} as it does not match its position",
+                fp.origin.icao, fp.destination.icao, track_data.ac_data.callsign
+            );
+            track_data.schedule_fp_retry();
+            return;
+        }
+
+        if should_cache {
+            if let Some(cache) = self.fp_cache.as_mut() {
+                cache.insert(&track_data.ac_data.callsign, &fp);
+            }
+        }
+
+        track_data.set_flightplan(fp, &self.airports, self.navdata.as_deref());
+    }
+
+    fn fail_flightplan(&mut self, id: &String, e: Error) {
+        let track_data = match self.tracking.get_mut(id) {
+            Some(d) => d,
+            None => return,
+        };
+
+        info!(
+            "Could not receive flight plan for {} because {:?}",
+            track_data.ac_data.callsign, e
+        );
+
+        track_data.fp_is_pending = false;
+        track_data.schedule_fp_retry();
     }
 
     /// Returns the original data if not passed in in an Option
@@ -107,6 +370,12 @@ impl Tracker {
             self.callsign_map
                 .insert(data.callsign.to_string(), id.clone());
 
+            if self.traces.running {
+                // Closest aircraft first, as with flight plans
+                let priority = self.center.distance_to(&data.get_position()) as u32;
+                self.traces.request_trace(id, &data.hex, priority);
+            }
+
             self.tracking
                 .insert(id.clone(), TrackData::new(id.clone(), data));
 
@@ -118,14 +387,26 @@ impl Tracker {
     // Removes aircraft that have been lost on radar
     fn remove_expired(&mut self) {
         let callsign_map = &mut self.callsign_map;
+        let mut did_remove = false;
 
         self.tracking.retain(|_, data| {
             let retain = data.at_last_position_update.elapsed().as_secs() < 10;
             if !retain {
                 callsign_map.remove(&data.ac_data.callsign);
+                did_remove = true;
             }
             return retain;
-        })
+        });
+
+        // No use fetching flight plans or traces for aircraft that left
+        if did_remove {
+            let callsign_map = &self.callsign_map;
+            self.flightplans
+                .cancel_requests(|callsign| callsign_map.contains_key(callsign));
+
+            let tracking = &self.tracking;
+            self.traces.cancel_requests(|id| tracking.contains_key(id));
+        }
     }
 
     fn get_next_aircraft_update(&mut self) -> Option<AircraftMap> {
@@ -146,6 +427,15 @@ impl Tracker {
     }
 
     fn update_position(&mut self, id: &str, new_ac_data: AircraftData) {
+        // The aircraft's own report is the most accurate for where it is
+        let wind = match (new_ac_data.wind_direction, new_ac_data.wind_speed) {
+            (Some(direction), Some(speed)) => Some(WindEstimate {
+                direction: direction as f32,
+                speed: speed as f32,
+            }),
+            _ => self.winds.get_band(new_ac_data.altitude).map(|x| x.wind),
+        };
+
         let current_data = match self.tracking.get_mut(id) {
             Some(d) => d,
             None => return,
@@ -162,6 +452,8 @@ impl Tracker {
             new_ac_data.ground_speed,
         );
         current_data.at_last_position_update = Instant::now();
+        current_data.push_history(TrackPoint::from(&new_ac_data));
+        current_data.wind = wind;
         current_data.ac_data = new_ac_data;
     }
 
@@ -187,11 +479,23 @@ impl Tracker {
                 continue;
             }
 
+            if let (Some(direction), Some(speed)) = (aircraft.wind_direction, aircraft.wind_speed) {
+                self.winds.add_observation(
+                    &aircraft.hex,
+                    aircraft.altitude,
+                    direction,
+                    speed,
+                    aircraft.temperature,
+                );
+            }
+
             if let Some(new_data) = self.check_and_create_new_aircraft(&id, aircraft) {
                 self.update_position(&id, new_data);
                 self.try_update_flightplan(&id);
             }
 
+            self.enrich_aircraft(&id);
+
             processed_ids.insert(id);
         }
 
@@ -200,13 +504,52 @@ impl Tracker {
 
     // Step
     fn step_flightplan(&mut self) {
-        if let Some(result) = self.faware.get_next_flightplan() {
+        if let Some(result) = self.flightplans.get_next_flightplan() {
             match result {
                 Ok(fp) => {
-                    self.update_flightplan(&fp.id, fp.fp);
-                    info!("Received flight plan for {}", fp.callsign);
+                    info!(
+                        "Received flight plan for {} from {}",
+                        fp.callsign, fp.source
+                    );
+                    self.update_flightplan(&fp.id, fp.fp, fp.should_cache);
+                }
+                Err((id, e)) => self.fail_flightplan(&id, e),
+            }
+        }
+    }
+
+    fn step_traces(&mut self) {
+        if let Some(result) = self.traces.get_next_trace() {
+            match result {
+                Ok(trace) => {
+                    if let Some(track_data) = self.tracking.get_mut(&trace.id) {
+                        info!(
+                            "Received {} trace points for {}",
+                            trace.points.len(),
+                            track_data.ac_data.callsign
+                        );
+                        track_data.seed_history(trace.points);
+                    }
+                }
+                Err(e) => info!("Could not receive trace because {:?}", e),
+            }
+        }
+    }
+
+    fn step_details(&mut self) {
+        if let Some(result) = self.fr24_details.get_next_details() {
+            match result {
+                Ok(result) => {
+                    for data in self.tracking.values_mut() {
+                        if data.ac_data.fr24_id == result.fr24_id {
+                            info!("Received flight details for {}", data.ac_data.callsign);
+                            result.details.apply(&mut data.ac_data);
+                        }
+                    }
+
+                    self.flight_details.insert(result.fr24_id, result.details);
                 }
-                Err(e) => info!("Could not receive flight plan because {:?}", e),
+                Err(e) => info!("Could not receive flight details because {:?}", e),
             }
         }
     }
@@ -237,6 +580,12 @@ impl Tracker {
 
         self.update_aircraft();
         self.step_flightplan();
+        self.step_traces();
+        self.step_details();
+
+        if let Some(cache) = self.fp_cache.as_mut() {
+            cache.flush_if_due();
+        }
     }
 
     pub fn start_buffering(&mut self) {
@@ -247,6 +596,10 @@ impl Tracker {
         self.is_buffering = false;
     }
 
+    pub fn get_winds(&self) -> &WindsAloft {
+        &self.winds
+    }
+
     pub fn is_buffering(&self) -> bool {
         self.is_buffering
     }
@@ -255,24 +608,277 @@ impl Tracker {
 pub struct TrackData {
     pub id: String,
     // Flight Plan
-    pub fp_did_try_update: bool,
     pub fp: Option<FlightPlan>,
+    pub fp_is_pending: bool,
+    pub fp_attempts: u32,
+    pub fp_retry_at: Option<Instant>,
+    // Origin and destination reported when the plan was requested
+    pub fp_route: (String, String),
+    // Flight plan route expanded into fixes, if nav data is loaded
+    pub route: Option<ParsedRoute>,
+    // Wind where the aircraft is
+    pub wind: Option<WindEstimate>,
     // Position
     pub at_last_position_update: Instant,
     pub position: InterpolatePosition,
+    // Oldest first
+    pub history: VecDeque<TrackPoint>,
     // Meta data
     pub ac_data: AircraftData,
 }
 
 impl TrackData {
     pub fn new(id: String, ac_data: AircraftData) -> Self {
+        let mut history = VecDeque::new();
+        history.push_back(TrackPoint::from(&ac_data));
+
         Self {
+            history,
             ac_data,
             id,
             fp: None,
-            fp_did_try_update: false,
+            fp_is_pending: false,
+            fp_attempts: 0,
+            fp_retry_at: None,
+            fp_route: (String::new(), String::new()),
+            route: None,
+            wind: None,
             at_last_position_update: Instant::now(),
             position: InterpolatePosition::default(),
         }
     }
+
+    fn set_flightplan(&mut self, fp: FlightPlan, airports: &Airports, navdata: Option<&NavData>) {
+        self.route = navdata.map(|navdata| {
+            let start = airports
+                .get_lat_lon(&fp.origin.icao)
+                .unwrap_or(self.ac_data.get_position());
+
+            let route =
+                navdata.parse_route(&fp.fp.route, &fp.origin.icao, &fp.destination.icao, start);
+
+            if !route.unknown.is_empty() {
+                info!(
+                    "Could not find {} in the route of {}",
+                    route.unknown.join(", "),
+                    self.ac_data.callsign
+                );
+            }
+
+            route
+        });
+
+        self.fp = Some(fp);
+    }
+
+    /// ICAO code of the destination from the flight plan, or the one reported by the provider
+    pub fn get_destination<'a>(&'a self, airports: &'a Airports) -> Option<&'a String> {
+        let destination = match &self.fp {
+            Some(fp) if fp.destination.icao != "" => &fp.destination.icao,
+            _ => &self.ac_data.destination,
+        };
+
+        if destination == "" {
+            return None;
+        }
+
+        Some(
+            airports
+                .get_icao_from_iata(destination)
+                .unwrap_or(destination),
+        )
+    }
+
+    pub fn is_arriving_at(&self, center: &LatLon, range: f32, airports: &Airports) -> bool {
+        self.get_destination(airports)
+            .and_then(|x| airports.get_lat_lon(x))
+            .map(|x| x.distance_to(center) <= range)
+            .unwrap_or(false)
+    }
+
+    /// Points left to fly from the current position to the destination,
+    /// following the expanded route when there is one
+    fn get_remaining_path(&self, airports: &Airports) -> Option<Vec<LatLon>> {
+        let destination = airports.get_lat_lon(self.get_destination(airports)?)?;
+        let position = self.ac_data.get_position();
+
+        let fixes = match &self.route {
+            Some(route) => &route.fixes[..],
+            None => &[],
+        };
+
+        // Continue from the closest fix, or the one after it if it has been passed
+        let closest = fixes
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                position
+                    .distance_to(&a.fix.position)
+                    .partial_cmp(&position.distance_to(&b.fix.position))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(i, x)| {
+                let bearing = position.bearing_to(&x.fix.position);
+                if heading_difference(self.ac_data.heading as f32, bearing) > 90.0 {
+                    i + 1
+                } else {
+                    i
+                }
+            })
+            .unwrap_or_default();
+
+        let mut path = vec![position];
+        path.extend(fixes.iter().skip(closest).map(|x| x.fix.position.clone()));
+        path.push(destination);
+
+        Some(path)
+    }
+
+    /// Distance left in nm
+    pub fn get_distance_to_go(&self, airports: &Airports) -> Option<f32> {
+        let path = self.get_remaining_path(airports)?;
+        Some(path.windows(2).map(|x| x[0].distance_to(&x[1])).sum())
+    }
+
+    /// Takes the wind on each remaining leg into account when it is known
+    pub fn get_eta(&self, airports: &Airports) -> Option<DateTime<Utc>> {
+        let ground_speed = self.ac_data.ground_speed as f32;
+        if self.ac_data.is_on_ground || ground_speed < MIN_ETA_GROUND_SPEED as f32 {
+            return None;
+        }
+
+        // Airspeed is assumed to stay the same while the wind changes with the course
+        let airspeed = match &self.wind {
+            Some(wind) => ground_speed - wind.get_tailwind(self.ac_data.heading as f32),
+            None => ground_speed,
+        };
+
+        let path = self.get_remaining_path(airports)?;
+        let hours: f32 = path
+            .windows(2)
+            .map(|x| {
+                let leg_ground_speed = match &self.wind {
+                    Some(wind) => airspeed + wind.get_tailwind(x[0].bearing_to(&x[1])),
+                    None => ground_speed,
+                };
+                x[0].distance_to(&x[1]) / leg_ground_speed.max(MIN_ETA_GROUND_SPEED as f32)
+            })
+            .sum();
+
+        Some(Utc::now() + chrono::Duration::seconds((hours * 3600.0) as i64))
+    }
+
+    fn schedule_fp_retry(&mut self) {
+        let delay = FP_RETRY_DELAY * 2u32.pow(self.fp_attempts.saturating_sub(1));
+        self.fp_retry_at = Some(Instant::now() + delay);
+    }
+
+    pub fn push_history(&mut self, point: TrackPoint) {
+        self.history.push_back(point);
+
+        while self.history.len() > MAX_HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    /// Prepends positions from before the aircraft was first tracked
+    pub fn seed_history(&mut self, points: Vec<TrackPoint>) {
+        let oldest = self
+            .history
+            .front()
+            .map(|x| x.timestamp)
+            .unwrap_or(u64::MAX);
+
+        for point in points
+            .into_iter()
+            .rev()
+            .skip_while(|x| x.timestamp >= oldest)
+        {
+            if self.history.len() >= MAX_HISTORY_LEN {
+                break;
+            }
+            self.history.push_front(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_route_changes() {
+        let requested = ("KBOS".to_string(), "KJFK".to_string());
+
+        assert!(!is_route_changed("KBOS", "KJFK", &requested));
+        assert!(is_route_changed("KBOS", "KEWR", &requested));
+        assert!(is_route_changed("KPVD", "KJFK", &requested));
+        // The provider dropping a side isn't a change
+        assert!(!is_route_changed("", "KJFK", &requested));
+    }
+
+    #[test]
+    fn ignores_route_filled_in_after_request() {
+        let requested = (String::new(), String::new());
+        assert!(!is_route_changed("KBOS", "KJFK", &requested));
+
+        let requested = ("KBOS".to_string(), String::new());
+        assert!(!is_route_changed("KBOS", "KJFK", &requested));
+        assert!(is_route_changed("KPVD", "KJFK", &requested));
+    }
+
+    #[test]
+    fn checks_flightplan_against_reported_route() {
+        use crate::flightplan::{Aircraft, Airport, PartialFlightPlan};
+
+        let airports = Airports::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/airports/full.csv"
+        ))
+        .unwrap();
+        let airport = |icao: &str| Airport {
+            icao: icao.to_string(),
+            ..Default::default()
+        };
+        let fp = FlightPlan {
+            origin: airport("KBOS"),
+            destination: airport("KBED"),
+            equipment: Aircraft::default(),
+            fp: PartialFlightPlan::default(),
+            arrival_time: None,
+            departure_time: None,
+            alternate: None,
+            fuel_minutes: None,
+        };
+        let reported = |origin: &str, destination: &str| AircraftData {
+            origin: origin.to_string(),
+            destination: destination.to_string(),
+            ..Default::default()
+        };
+
+        assert!(matches_reported_route(
+            &fp,
+            &reported("KBOS", "KBED"),
+            &airports
+        ));
+        assert!(matches_reported_route(
+            &fp,
+            &reported("BOS", "BED"),
+            &airports
+        ));
+        // Nothing reported to compare with
+        assert!(matches_reported_route(&fp, &reported("", ""), &airports));
+        assert!(matches_reported_route(&fp, &reported("BOS", ""), &airports));
+
+        assert!(!matches_reported_route(
+            &fp,
+            &reported("BED", "BOS"),
+            &airports
+        ));
+        assert!(!matches_reported_route(
+            &fp,
+            &reported("KBOS", "KJFK"),
+            &airports
+        ));
+    }
 }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Deserialize;
 
 use crate::error::Error;
 
@@ -20,6 +21,10 @@ pub fn convert_miles_to_lon(miles: f32) -> f32 {
     return miles / 54.6;
 }
 
+pub fn is_airline_callsign(callsign: &str) -> bool {
+    AIRLINE_REGEX.is_match(callsign)
+}
+
 pub fn is_valid_callsign(callsign: &str) -> bool {
     CALLSIGN_REGEX.is_match(callsign) || REGISTRATION_REGEX.is_match(callsign)
 }
@@ -30,6 +35,43 @@ pub struct LatLon {
     pub lon: f32,
 }
 
+const EARTH_RADIUS_NM: f32 = 3440.065;
+const STANDARD_ALTIMETER_INHG: f32 = 29.92;
+// Close enough to the real lapse near the surface
+const FEET_PER_INHG: f32 = 1000.0;
+
+impl LatLon {
+    /// Great circle distance in nautical miles
+    pub fn distance_to(&self, other: &LatLon) -> f32 {
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_NM * a.sqrt().min(1.0).asin()
+    }
+
+    /// Initial true bearing towards another point, in degrees
+    pub fn bearing_to(&self, other: &LatLon) -> f32 {
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let y = d_lon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
+    }
+}
+
+/// Smallest difference between two headings in degrees
+pub fn heading_difference(a: f32, b: f32) -> f32 {
+    let diff = (a - b).abs() % 360.0;
+    diff.min(360.0 - diff)
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Vector2D {
     pub x: f32,
@@ -56,7 +98,50 @@ pub struct Bounds {
     pub lon2: f32,
 }
 
-#[derive(Debug)]
+impl Bounds {
+    pub fn center(&self) -> LatLon {
+        LatLon {
+            lat: (self.lat1 + self.lat2) / 2.0,
+            lon: (self.lon1 + self.lon2) / 2.0,
+        }
+    }
+
+    /// Splits the bounds into four equally sized quadrants
+    pub fn split(&self) -> Vec<Bounds> {
+        let mid_lat = (self.lat1 + self.lat2) / 2.0;
+        let mid_lon = (self.lon1 + self.lon2) / 2.0;
+
+        vec![
+            Bounds {
+                lat1: self.lat1,
+                lon1: self.lon1,
+                lat2: mid_lat,
+                lon2: mid_lon,
+            },
+            Bounds {
+                lat1: self.lat1,
+                lon1: mid_lon,
+                lat2: mid_lat,
+                lon2: self.lon2,
+            },
+            Bounds {
+                lat1: mid_lat,
+                lon1: self.lon1,
+                lat2: self.lat2,
+                lon2: mid_lon,
+            },
+            Bounds {
+                lat1: mid_lat,
+                lon1: mid_lon,
+                lat2: self.lat2,
+                lon2: self.lon2,
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
 pub struct AircraftData {
     pub squawk: String,
     pub callsign: String,
@@ -71,11 +156,36 @@ pub struct AircraftData {
     pub hex: String,
     pub origin: String,
     pub destination: String,
+    pub registration: String,
+    pub airline: String,
+    // Flight number, such as B6123
+    pub flight: String,
+    // Feet per minute
+    pub vertical_rate: i32,
+    // Receiver or radar the position came from
+    pub radar: String,
+    // FlightRadar24's identifier for this flight
+    pub fr24_id: String,
+    // Weather downlinked by the aircraft, degrees and knots
+    pub wind_direction: Option<u32>,
+    pub wind_speed: Option<u32>,
+    // Outside air temperature in Celsius
+    pub temperature: Option<i32>,
+    // GNSS altitude in feet, only reported by some sources. This is height above the WGS84
+    // ellipsoid, not mean sea level, and can be off by a hundred feet or more depending on the geoid.
+    pub geometric_altitude: Option<i32>,
 }
 
 impl AircraftData {
+    pub fn get_position(&self) -> LatLon {
+        LatLon {
+            lat: self.latitude,
+            lon: self.longitude,
+        }
+    }
+
     pub fn is_airline(&self) -> bool {
-        AIRLINE_REGEX.is_match(&self.callsign)
+        is_airline_callsign(&self.callsign)
     }
 
     pub fn get_airline(&self) -> Option<&str> {
@@ -109,6 +219,70 @@ impl AircraftData {
             hex: self.hex,
             origin: replace_if!(self.origin == "", origin),
             destination: replace_if!(self.destination == "", destination),
+            registration: replace_if!(self.registration == "", registration),
+            airline: replace_if!(self.airline == "", airline),
+            flight: replace_if!(self.flight == "", flight),
+            vertical_rate: replace_if!(self.vertical_rate == 0 && update_space, vertical_rate),
+            radar: self.radar,
+            fr24_id: replace_if!(self.fr24_id == "", fr24_id),
+            wind_direction: replace_if!(self.wind_direction.is_none(), wind_direction),
+            wind_speed: replace_if!(self.wind_speed.is_none(), wind_speed),
+            temperature: replace_if!(self.temperature.is_none(), temperature),
+            geometric_altitude: replace_if!(
+                self.geometric_altitude.is_none() && update_space,
+                geometric_altitude
+            ),
+        }
+    }
+
+    /// Altitude as read on an altimeter, pressure altitude corrected to QNH below the transition altitude
+    pub fn get_indicated_altitude(
+        &self,
+        altimeter_inhg: Option<f32>,
+        transition_altitude: i32,
+        use_geometric_altitude: bool,
+    ) -> i32 {
+        // Flight levels are always flown on standard pressure
+        if self.altitude >= transition_altitude {
+            return self.altitude;
+        }
+
+        // Not corrected to MSL, the geoid height isn't known here
+        if let Some(geometric) = self.geometric_altitude.filter(|_| use_geometric_altitude) {
+            return geometric;
+        }
+
+        match altimeter_inhg {
+            Some(inhg) => {
+                self.altitude + ((inhg - STANDARD_ALTIMETER_INHG) * FEET_PER_INHG).round() as i32
+            }
+            None => self.altitude,
+        }
+    }
+}
+
+/// A single observed position, used to build an aircraft's history
+#[derive(Debug, Clone)]
+pub struct TrackPoint {
+    pub timestamp: u64,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub altitude: i32,
+    pub ground_speed: u32,
+    pub heading: u32,
+    pub is_on_ground: bool,
+}
+
+impl From<&AircraftData> for TrackPoint {
+    fn from(data: &AircraftData) -> Self {
+        Self {
+            timestamp: data.timestamp,
+            latitude: data.latitude,
+            longitude: data.longitude,
+            altitude: data.altitude,
+            ground_speed: data.ground_speed,
+            heading: data.heading,
+            is_on_ground: data.is_on_ground,
         }
     }
 }
@@ -119,3 +293,96 @@ pub trait AircraftProvider {
 }
 
 pub type AircraftMap = HashMap<String, AircraftData>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_aircraft(altitude: i32, geometric_altitude: Option<i32>) -> AircraftData {
+        AircraftData {
+            altitude,
+            geometric_altitude,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn corrects_altitude_below_transition() {
+        let aircraft = get_aircraft(5000, Some(5400));
+
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(30.42), 18000, false),
+            5500
+        );
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(29.52), 18000, false),
+            4600
+        );
+        // Without a METAR the pressure altitude is all there is
+        assert_eq!(aircraft.get_indicated_altitude(None, 18000, false), 5000);
+    }
+
+    #[test]
+    fn keeps_pressure_altitude_above_transition() {
+        let aircraft = get_aircraft(18000, Some(18600));
+
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(30.42), 18000, false),
+            18000
+        );
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(30.42), 18000, true),
+            18000
+        );
+    }
+
+    #[test]
+    fn prefers_geometric_altitude_when_enabled() {
+        let aircraft = get_aircraft(5000, Some(5400));
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(30.42), 18000, true),
+            5400
+        );
+        assert_eq!(aircraft.get_indicated_altitude(None, 18000, true), 5400);
+
+        // Falls back to the altimeter when the source has no GNSS altitude
+        let aircraft = get_aircraft(5000, None);
+        assert_eq!(
+            aircraft.get_indicated_altitude(Some(30.42), 18000, true),
+            5500
+        );
+    }
+
+    #[test]
+    fn splits_bounds_into_quadrants() {
+        let bounds = Bounds {
+            lat1: 43.0,
+            lon1: -72.0,
+            lat2: 42.0,
+            lon2: -70.0,
+        };
+        let quadrants = bounds.split();
+
+        assert_eq!(quadrants.len(), 4);
+        for quadrant in quadrants.iter() {
+            assert_eq!((quadrant.lat1 - quadrant.lat2).abs(), 0.5);
+            assert_eq!((quadrant.lon1 - quadrant.lon2).abs(), 1.0);
+        }
+
+        // Together they cover the whole area without overlapping
+        let mut corners: Vec<(i32, i32)> = quadrants
+            .iter()
+            .map(|x| {
+                (
+                    (x.center().lat * 100.0) as i32,
+                    (x.center().lon * 100.0) as i32,
+                )
+            })
+            .collect();
+        corners.sort();
+        assert_eq!(
+            corners,
+            vec![(4225, -7150), (4225, -7050), (4275, -7150), (4275, -7050)]
+        );
+    }
+}
@@ -0,0 +1,592 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::airports::Airports;
+use crate::error::Error;
+use crate::metar::Metar;
+use crate::noaa::NoaaWeather;
+use crate::request::Request;
+use crate::taf::Taf;
+use crate::weatherfile::WeatherFile;
+
+// Cached METARs older than this are fetched again
+const METAR_TTL: Duration = Duration::from_secs(5 * 60);
+// TAFs are only issued every few hours
+const TAF_TTL: Duration = Duration::from_secs(30 * 60);
+// Most stations asked for in one request
+const MAX_BATCH_SIZE: usize = 50;
+// How far away in nm a station can be to stand in for one without a METAR
+const NEAREST_STATION_RADIUS: f32 = 50.0;
+// Closest airports tried, most small fields don't report
+const MAX_NEAREST_STATIONS: usize = 20;
+// Wait after a failed fetch, doubled after every failure in a row
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+pub trait WeatherSource {
+    fn get_metars(&self, stations: &[String]) -> Result<Vec<Metar>, Error>;
+    fn get_tafs(&self, stations: &[String]) -> Result<Vec<Taf>, Error>;
+    fn get_name(&self) -> &str;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum WeatherConfig {
+    Noaa,
+    // Reports are read from text files, for fixed weather or offline use
+    File {
+        #[serde(default)]
+        metar_file: Option<String>,
+        #[serde(default)]
+        taf_file: Option<String>,
+    },
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self::Noaa
+    }
+}
+
+impl WeatherConfig {
+    pub fn create_source(&self) -> Arc<dyn WeatherSource + Send + Sync> {
+        match self {
+            Self::Noaa => Arc::new(NoaaWeather::new()),
+            Self::File {
+                metar_file,
+                taf_file,
+            } => Arc::new(WeatherFile::new(metar_file.clone(), taf_file.clone())),
+        }
+    }
+}
+
+trait Report: Clone + Send + 'static {
+    fn get_station(&self) -> &str;
+    fn get_raw(&self) -> &str;
+}
+
+impl Report for Metar {
+    fn get_station(&self) -> &str {
+        &self.station
+    }
+
+    fn get_raw(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl Report for Taf {
+    fn get_station(&self) -> &str {
+        &self.station
+    }
+
+    fn get_raw(&self) -> &str {
+        &self.raw
+    }
+}
+
+struct CachedReport<T> {
+    // None if the station had no report
+    report: Option<T>,
+    checked_at: Instant,
+}
+
+type ReportResult<T> = (Vec<String>, Result<Vec<T>, Error>);
+
+/// Cache of one kind of report, refreshed in batches
+struct Reports<T: Report> {
+    request: Request<ReportResult<T>, Vec<String>>,
+    ttl: Duration,
+    cache: HashMap<String, CachedReport<T>>,
+    // Stations kept up to date in the background
+    watched: HashSet<String>,
+    // Stations a client is waiting on
+    requested: HashSet<String>,
+    // Stations fetched for the cache only
+    prefetched: HashSet<String>,
+    in_flight: HashSet<String>,
+    ready: VecDeque<T>,
+    // Requested stations that turned out to have no report
+    missing: Vec<String>,
+    // Fetches in a row that failed, nothing is sent again before retry_at
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl<T: Report> Reports<T> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            request: Request::new(1),
+            ttl,
+            cache: HashMap::new(),
+            watched: HashSet::new(),
+            requested: HashSet::new(),
+            prefetched: HashSet::new(),
+            in_flight: HashSet::new(),
+            ready: VecDeque::new(),
+            missing: Vec::new(),
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    fn run<F>(&self, fetch: F)
+    where
+        F: Fn(&[String]) -> Result<Vec<T>, Error> + Send + Sync + 'static,
+    {
+        self.request
+            .run(move |stations| (stations.clone(), fetch(&stations)));
+    }
+
+    fn watch(&mut self, icao: &str) {
+        self.watched.insert(icao.to_uppercase());
+    }
+
+    fn request(&mut self, icao: &str) {
+        let icao = icao.to_uppercase();
+
+        match self.cache.get(&icao) {
+            Some(cached) if cached.checked_at.elapsed() < self.ttl => match &cached.report {
+                Some(report) => self.ready.push_back(report.clone()),
+                None => self.missing.push(icao),
+            },
+            _ => {
+                self.requested.insert(icao);
+            }
+        }
+    }
+
+    /// Fetches the station without sending its report anywhere
+    fn prefetch(&mut self, icao: &str) {
+        if self.is_stale(&icao.to_string()) {
+            self.prefetched.insert(icao.to_uppercase());
+        }
+    }
+
+    /// Some(None) if the station is known to have no report, None if it hasn't been checked yet
+    fn get_cached(&self, icao: &str) -> Option<Option<&T>> {
+        if self.in_flight.contains(icao) || self.prefetched.contains(icao) {
+            return None;
+        }
+
+        Some(self.cache.get(icao)?.report.as_ref())
+    }
+
+    fn is_stale(&self, icao: &String) -> bool {
+        self.cache
+            .get(icao)
+            .map(|x| x.checked_at.elapsed() >= self.ttl)
+            .unwrap_or(true)
+    }
+
+    fn update_cache(&mut self, report: T) {
+        let station = report.get_station().to_string();
+        let was_requested = self.requested.remove(&station);
+        let is_changed = self
+            .cache
+            .get(&station)
+            .and_then(|x| x.report.as_ref())
+            .map(|x| x.get_raw() != report.get_raw())
+            .unwrap_or(true);
+
+        self.cache.insert(
+            station,
+            CachedReport {
+                report: Some(report.clone()),
+                checked_at: Instant::now(),
+            },
+        );
+
+        if was_requested || (is_changed && self.watched.contains(report.get_station())) {
+            self.ready.push_back(report);
+        }
+    }
+
+    fn step(&mut self, kind: &str) {
+        let is_waiting = self.retry_at.map(|x| Instant::now() < x).unwrap_or(false);
+        if !is_waiting {
+            self.send_stale();
+        }
+
+        while let Some((stations, result)) = self.request.get_next() {
+            for station in stations.iter() {
+                self.in_flight.remove(station);
+            }
+
+            match result {
+                Ok(reports) => {
+                    self.failures = 0;
+                    self.retry_at = None;
+                    self.update_stations(kind, stations, reports);
+                }
+                // Nothing is known about the stations, so they are left to be fetched again
+                Err(e) => {
+                    warn!(
+                        "Could not fetch {}s for {}: {:?}",
+                        kind,
+                        stations.join(","),
+                        e
+                    );
+
+                    let delay = (RETRY_DELAY * 2u32.pow(self.failures.min(8))).min(MAX_RETRY_DELAY);
+                    self.failures += 1;
+                    self.retry_at = Some(Instant::now() + delay);
+                }
+            }
+        }
+    }
+
+    fn send_stale(&mut self) {
+        let mut stale: Vec<String> = self
+            .watched
+            .iter()
+            .chain(self.requested.iter())
+            .chain(self.prefetched.iter())
+            .filter(|x| !self.in_flight.contains(*x) && self.is_stale(x))
+            .cloned()
+            .collect();
+        stale.sort();
+        stale.dedup();
+
+        for batch in stale.chunks(MAX_BATCH_SIZE) {
+            self.in_flight.extend(batch.iter().cloned());
+            self.request.give_job(batch.to_vec());
+        }
+    }
+
+    fn update_stations(&mut self, kind: &str, stations: Vec<String>, reports: Vec<T>) {
+        for report in reports {
+            self.update_cache(report);
+        }
+
+        // Stations without a report aren't asked for again until they expire
+        for station in stations {
+            self.prefetched.remove(&station);
+
+            if self.requested.remove(&station) {
+                info!("No {} found for {}", kind, station);
+                self.missing.push(station.clone());
+            }
+
+            let cached = self.cache.entry(station).or_insert(CachedReport {
+                report: None,
+                checked_at: Instant::now(),
+            });
+            cached.checked_at = Instant::now();
+        }
+    }
+}
+
+/// METAR of the closest station to one that has none
+#[derive(Debug)]
+pub struct NearestMetar {
+    pub requested: String,
+    // nm
+    pub distance: f32,
+    pub metar: Metar,
+}
+
+/// METARs and TAFs from a weather source, cached and refreshed in the background
+pub struct Weather {
+    source: Arc<dyn WeatherSource + Send + Sync>,
+    airports: Arc<Airports>,
+    metars: Reports<Metar>,
+    tafs: Reports<Taf>,
+    // Stations without a METAR to the stations around them, closest first
+    nearest: HashMap<String, Vec<(String, f32)>>,
+    nearest_ready: VecDeque<NearestMetar>,
+}
+
+impl Weather {
+    pub fn new(source: Arc<dyn WeatherSource + Send + Sync>, airports: Arc<Airports>) -> Self {
+        info!("Using weather from {}", source.get_name());
+
+        Self {
+            source,
+            airports,
+            metars: Reports::new(METAR_TTL),
+            tafs: Reports::new(TAF_TTL),
+            nearest: HashMap::new(),
+            nearest_ready: VecDeque::new(),
+        }
+    }
+
+    pub fn run(&self) {
+        let source = self.source.clone();
+        self.metars.run(move |stations| source.get_metars(stations));
+
+        let source = self.source.clone();
+        self.tafs.run(move |stations| source.get_tafs(stations));
+    }
+
+    /// Keeps the station's METAR refreshed without a client asking for it
+    pub fn watch(&mut self, icao: &str) {
+        self.metars.watch(icao);
+    }
+
+    /// The station's METAR is sent now and again whenever it changes
+    pub fn request_weather(&mut self, icao: &str) {
+        self.metars.watch(icao);
+        self.metars.request(icao);
+    }
+
+    /// The TAF is only sent once, they are asked for now and then rather than followed
+    pub fn request_taf(&mut self, icao: &str) {
+        self.tafs.request(icao);
+    }
+
+    /// Sends stale stations off in batches and takes in the results
+    pub fn step(&mut self) {
+        self.metars.step("METAR");
+        self.tafs.step("TAF");
+
+        for station in std::mem::take(&mut self.metars.missing) {
+            self.find_nearest(station);
+        }
+
+        self.step_nearest();
+    }
+
+    fn find_nearest(&mut self, station: String) {
+        let position = match self.airports.get_lat_lon(&station) {
+            Some(p) => p,
+            None => return,
+        };
+
+        let candidates: Vec<(String, f32)> = self
+            .airports
+            .get_airports_near(&position, NEAREST_STATION_RADIUS)
+            .into_iter()
+            .filter(|(icao, _)| {
+                **icao != station
+                    && icao.len() == 4
+                    && icao.chars().all(|x| x.is_ascii_alphabetic())
+            })
+            .take(MAX_NEAREST_STATIONS)
+            .map(|(icao, distance)| (icao.clone(), distance))
+            .collect();
+
+        for (icao, _) in candidates.iter() {
+            self.metars.prefetch(icao);
+        }
+
+        self.nearest.insert(station, candidates);
+    }
+
+    /// Picks the closest station once all around it have been checked
+    fn step_nearest(&mut self) {
+        let metars = &self.metars;
+        let mut done = Vec::new();
+
+        for (station, candidates) in self.nearest.iter() {
+            let checked: Option<Vec<Option<&Metar>>> = candidates
+                .iter()
+                .map(|(icao, _)| metars.get_cached(icao))
+                .collect();
+
+            let checked = match checked {
+                Some(c) => c,
+                None => continue,
+            };
+
+            done.push(station.clone());
+
+            let closest = candidates
+                .iter()
+                .zip(checked)
+                .find_map(|((_, distance), metar)| Some((*distance, metar?.clone())));
+
+            match closest {
+                Some((distance, metar)) => self.nearest_ready.push_back(NearestMetar {
+                    requested: station.clone(),
+                    distance,
+                    metar,
+                }),
+                None => info!("No METAR found near {}", station),
+            }
+        }
+
+        for station in done {
+            self.nearest.remove(&station);
+        }
+    }
+
+    /// METARs that were asked for or have changed since they were last sent
+    pub fn get_next_weather(&mut self) -> Option<Metar> {
+        self.metars.ready.pop_front()
+    }
+
+    /// Stand-in METARs for requested stations that have none
+    pub fn get_next_nearest_weather(&mut self) -> Option<NearestMetar> {
+        self.nearest_ready.pop_front()
+    }
+
+    pub fn get_next_taf(&mut self) -> Option<Taf> {
+        self.tafs.ready.pop_front()
+    }
+
+    /// Requested stations that have no TAF
+    pub fn get_next_missing_taf(&mut self) -> Option<String> {
+        self.tafs.missing.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_fixture(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/weather/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+
+    fn get_weather() -> Weather {
+        let source = WeatherConfig::File {
+            metar_file: Some(get_fixture("metars.txt")),
+            taf_file: None,
+        }
+        .create_source();
+        let airports = Airports::new(&get_fixture("airports.csv")).unwrap();
+
+        Weather::new(source, Arc::new(airports))
+    }
+
+    #[test]
+    fn uses_closest_reporting_station() {
+        let mut weather = get_weather();
+        weather.run();
+        weather.request_weather("KXYZ");
+
+        let started = Instant::now();
+        let nearest = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "no reply");
+            weather.step();
+
+            // Nothing is sent while any of the stations around are still being checked
+            let candidates = weather.nearest.get("KXYZ").cloned().unwrap_or_default();
+            if candidates
+                .iter()
+                .any(|(icao, _)| weather.metars.get_cached(icao).is_none())
+            {
+                assert!(weather.nearest_ready.is_empty());
+            }
+
+            if let Some(nearest) = weather.get_next_nearest_weather() {
+                break nearest;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(nearest.requested, "KXYZ");
+        // KAAA is closer but has no report, KFAR is too far away
+        assert_eq!(nearest.metar.station, "KBBB");
+        assert!((nearest.distance - 12.0).abs() < 0.5);
+        for icao in ["KAAA", "KBBB", "KCCC"].iter() {
+            assert!(weather.metars.cache.contains_key(*icao));
+        }
+        assert!(!weather.metars.cache.contains_key("KFAR"));
+        assert!(weather.get_next_weather().is_none());
+    }
+
+    #[test]
+    fn waits_for_candidates_in_flight() {
+        // Not running, so jobs stay queued
+        let mut weather = get_weather();
+        weather.metars.prefetch("KCCC");
+        weather.metars.step("METAR");
+        weather.find_nearest("KXYZ".to_string());
+
+        // KCCC was already on its way, so the reply waits for it too
+        assert!(weather.metars.in_flight.contains("KCCC"));
+        weather.step_nearest();
+        assert!(weather.get_next_nearest_weather().is_none());
+        assert_eq!(weather.nearest["KXYZ"].len(), 3);
+    }
+
+    // Sends the stale stations and steps until the worker has answered
+    fn step_until_answered(reports: &mut Reports<Metar>) {
+        let started = Instant::now();
+        reports.step("METAR");
+
+        while !reports.in_flight.is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(5), "no answer");
+            std::thread::sleep(Duration::from_millis(10));
+            reports.step("METAR");
+        }
+    }
+
+    #[test]
+    fn keeps_requests_when_fetch_fails() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let is_down = Arc::new(AtomicBool::new(true));
+        let mut reports: Reports<Metar> = Reports::new(METAR_TTL);
+        {
+            let is_down = is_down.clone();
+            reports.run(move |_| {
+                if is_down.load(Ordering::SeqCst) {
+                    Err(Error::InvalidData("server down"))
+                } else {
+                    Ok(vec![Metar::parse(
+                        "KBOS 181454Z 27015KT 10SM CLR 18/M02 A2992",
+                    )])
+                }
+            });
+        }
+
+        reports.request("KBOS");
+        reports.prefetch("KBED");
+        step_until_answered(&mut reports);
+
+        // Nothing is cached or given up on, the stations wait for the retry
+        assert!(reports.cache.is_empty());
+        assert!(reports.missing.is_empty());
+        assert!(reports.requested.contains("KBOS"));
+        assert!(reports.get_cached("KBED").is_none());
+        assert!(reports.retry_at.is_some());
+        assert_eq!(reports.failures, 1);
+
+        // Not sent again before the retry is due
+        reports.step("METAR");
+        assert!(reports.in_flight.is_empty());
+
+        is_down.store(false, Ordering::SeqCst);
+        reports.retry_at = None;
+        step_until_answered(&mut reports);
+
+        assert_eq!(reports.failures, 0);
+        assert_eq!(reports.ready.pop_front().unwrap().station, "KBOS");
+        // Left out of a successful response, so known to have no report
+        assert!(matches!(reports.get_cached("KBED"), Some(None)));
+        assert!(reports.missing.is_empty());
+    }
+
+    #[test]
+    fn reports_stations_without_taf() {
+        let mut weather = get_weather();
+        weather.run();
+        weather.request_taf("kbos");
+
+        let started = Instant::now();
+        let station = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "no reply");
+            weather.step();
+
+            if let Some(station) = weather.get_next_missing_taf() {
+                break station;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(station, "KBOS");
+        assert!(weather.get_next_taf().is_none());
+        // Asked for once, not kept up to date
+        assert!(weather.tafs.watched.is_empty());
+    }
+}
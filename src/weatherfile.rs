@@ -0,0 +1,112 @@
+use std::fs;
+
+use crate::error::Error;
+use crate::metar::Metar;
+use crate::taf::Taf;
+use crate::weather::WeatherSource;
+
+/// Splits text into reports, one per line. Indented lines continue the report above them.
+fn split_reports(text: &str) -> Vec<String> {
+    let mut reports: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        if line.trim() == "" {
+            continue;
+        }
+
+        match reports.last_mut() {
+            Some(report) if line.starts_with(char::is_whitespace) => {
+                report.push(' ');
+                report.push_str(line.trim());
+            }
+            _ => reports.push(line.trim().to_string()),
+        }
+    }
+
+    reports
+}
+
+fn read_reports<T>(
+    filename: &Option<String>,
+    stations: &[String],
+    parse: fn(&str) -> T,
+    get_station: fn(&T) -> &str,
+) -> Result<Vec<T>, Error> {
+    let filename = match filename {
+        Some(f) => f,
+        None => return Ok(Vec::new()),
+    };
+
+    // Read every time so edits show up without a restart
+    let text = fs::read_to_string(filename)?;
+
+    Ok(split_reports(&text)
+        .iter()
+        .map(|x| parse(x))
+        .filter(|x| stations.iter().any(|s| s == get_station(x)))
+        .collect())
+}
+
+/// Fixed weather read from text files
+pub struct WeatherFile {
+    metar_file: Option<String>,
+    taf_file: Option<String>,
+}
+
+impl WeatherFile {
+    pub fn new(metar_file: Option<String>, taf_file: Option<String>) -> Self {
+        Self {
+            metar_file,
+            taf_file,
+        }
+    }
+}
+
+impl WeatherSource for WeatherFile {
+    fn get_metars(&self, stations: &[String]) -> Result<Vec<Metar>, Error> {
+        read_reports(&self.metar_file, stations, Metar::parse, |x| &x.station)
+    }
+
+    fn get_tafs(&self, stations: &[String]) -> Result<Vec<Taf>, Error> {
+        read_reports(&self.taf_file, stations, Taf::parse, |x| &x.station)
+    }
+
+    fn get_name(&self) -> &str {
+        "weather files"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_multiline_reports() {
+        let text = "TAF KBOS 181120Z 1812/1918 27015KT P6SM FEW050\n  FM181800 29012KT P6SM SCT060\n\nTAF KJFK 181120Z 1812/1918 VRB03KT P6SM SKC\n";
+
+        assert_eq!(
+            split_reports(text),
+            vec![
+                "TAF KBOS 181120Z 1812/1918 27015KT P6SM FEW050 FM181800 29012KT P6SM SCT060",
+                "TAF KJFK 181120Z 1812/1918 VRB03KT P6SM SKC",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_requested_stations() {
+        let filename = std::env::temp_dir().join("livetraffic_metars.txt");
+        fs::write(
+            &filename,
+            "KBOS 181454Z 27015KT 10SM FEW050 18/M02 A2992\nKJFK 181451Z 31010KT 10SM SKC 20/01 A2990\n",
+        )
+        .unwrap();
+
+        let source = WeatherFile::new(Some(filename.to_string_lossy().to_string()), None);
+        let metars = source.get_metars(&["KJFK".to_string()]).unwrap();
+
+        assert_eq!(metars.len(), 1);
+        assert_eq!(metars[0].station, "KJFK");
+        assert!(source.get_tafs(&["KJFK".to_string()]).unwrap().is_empty());
+    }
+}
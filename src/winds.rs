@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+// Observations are grouped into layers this many feet thick
+const BAND_HEIGHT: i32 = 2000;
+// Older reports no longer describe the current winds
+const OBSERVATION_TTL: Duration = Duration::from_secs(30 * 60);
+// Reports from aircraft close to the ground are mostly noise
+const MIN_ALTITUDE: i32 = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WindEstimate {
+    // Degrees the wind is blowing from
+    pub direction: f32,
+    // Knots
+    pub speed: f32,
+}
+
+impl WindEstimate {
+    /// Wind component along the course, positive for a tailwind
+    pub fn get_tailwind(&self, course: f32) -> f32 {
+        -self.speed * (self.direction - course).to_radians().cos()
+    }
+}
+
+struct Observation {
+    time: Instant,
+    // Wind as a vector pointing to where it blows from, so it can be averaged
+    x: f32,
+    y: f32,
+    temperature: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindBand {
+    // Bottom of the layer in feet
+    pub altitude: i32,
+    pub wind: WindEstimate,
+    // Degrees Celsius
+    pub temperature: Option<f32>,
+    pub observations: usize,
+}
+
+/// Winds and temperatures reported by aircraft in range, by altitude
+#[derive(Default)]
+pub struct WindsAloft {
+    // Latest report of each aircraft, so ones seen on every poll don't outweigh the rest
+    bands: BTreeMap<i32, HashMap<String, Observation>>,
+}
+
+fn get_band_altitude(altitude: i32) -> i32 {
+    altitude.div_euclid(BAND_HEIGHT) * BAND_HEIGHT
+}
+
+fn summarize(altitude: i32, observations: &HashMap<String, Observation>) -> Option<WindBand> {
+    let recent: Vec<&Observation> = observations
+        .values()
+        .filter(|x| x.time.elapsed() < OBSERVATION_TTL)
+        .collect();
+
+    if recent.is_empty() {
+        return None;
+    }
+
+    let count = recent.len() as f32;
+    let x = recent.iter().map(|o| o.x).sum::<f32>() / count;
+    let y = recent.iter().map(|o| o.y).sum::<f32>() / count;
+
+    let temperatures: Vec<i32> = recent.iter().filter_map(|o| o.temperature).collect();
+    let temperature = if temperatures.is_empty() {
+        None
+    } else {
+        Some(temperatures.iter().sum::<i32>() as f32 / temperatures.len() as f32)
+    };
+
+    Some(WindBand {
+        altitude,
+        wind: WindEstimate {
+            direction: x.atan2(y).to_degrees().rem_euclid(360.0),
+            speed: x.hypot(y),
+        },
+        temperature,
+        observations: recent.len(),
+    })
+}
+
+impl WindsAloft {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_observation(
+        &mut self,
+        hex: &str,
+        altitude: i32,
+        direction: u32,
+        speed: u32,
+        temperature: Option<i32>,
+    ) {
+        if altitude < MIN_ALTITUDE {
+            return;
+        }
+
+        let radians = (direction as f32).to_radians();
+        let observations = self.bands.entry(get_band_altitude(altitude)).or_default();
+
+        observations.retain(|_, x| x.time.elapsed() < OBSERVATION_TTL);
+        observations.insert(
+            hex.to_string(),
+            Observation {
+                time: Instant::now(),
+                x: speed as f32 * radians.sin(),
+                y: speed as f32 * radians.cos(),
+                temperature,
+            },
+        );
+    }
+
+    pub fn get_band(&self, altitude: i32) -> Option<WindBand> {
+        let band_altitude = get_band_altitude(altitude);
+        summarize(band_altitude, self.bands.get(&band_altitude)?)
+    }
+
+    /// Bands with recent reports, lowest first
+    pub fn get_profile(&self) -> Vec<WindBand> {
+        self.bands
+            .iter()
+            .filter_map(|(altitude, observations)| summarize(*altitude, observations))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn averages_as_vectors() {
+        let mut winds = WindsAloft::new();
+        // Either side of north, an average of the angles would give 180
+        winds.add_observation("a", 35000, 350, 40, Some(-50));
+        winds.add_observation("b", 35500, 10, 40, Some(-52));
+
+        let band = winds.get_band(34000).unwrap();
+        assert_eq!(band.altitude, 34000);
+        assert_eq!(band.observations, 2);
+        assert_close(band.wind.direction.min(360.0 - band.wind.direction), 0.0);
+        assert_close(band.wind.speed, 40.0 * 10f32.to_radians().cos());
+        assert_close(band.temperature.unwrap(), -51.0);
+
+        // Opposing winds cancel out
+        winds.add_observation("c", 12000, 90, 20, None);
+        winds.add_observation("d", 12000, 270, 20, None);
+        assert_close(winds.get_band(12000).unwrap().wind.speed, 0.0);
+    }
+
+    #[test]
+    fn keeps_latest_report_per_aircraft() {
+        let mut winds = WindsAloft::new();
+        for _ in 0..10 {
+            winds.add_observation("a", 20000, 270, 50, None);
+        }
+        winds.add_observation("a", 20000, 280, 60, None);
+        winds.add_observation("b", 20000, 280, 60, None);
+        // Too low to count
+        winds.add_observation("c", 500, 90, 10, None);
+
+        let profile = winds.get_profile();
+        assert_eq!(profile.len(), 1);
+        assert_eq!(profile[0].observations, 2);
+        assert_close(profile[0].wind.direction, 280.0);
+        assert_close(profile[0].wind.speed, 60.0);
+    }
+
+    #[test]
+    fn drops_old_reports() {
+        let mut winds = WindsAloft::new();
+        winds.add_observation("a", 20000, 270, 50, None);
+        winds.add_observation("b", 30000, 270, 80, None);
+
+        let old = Instant::now()
+            .checked_sub(OBSERVATION_TTL)
+            .expect("clock too close to its start");
+        winds
+            .bands
+            .get_mut(&20000)
+            .unwrap()
+            .get_mut("a")
+            .unwrap()
+            .time = old;
+
+        assert!(winds.get_band(20000).is_none());
+        assert_eq!(winds.get_profile().len(), 1);
+
+        // Removed once the band is written to again
+        winds.add_observation("c", 20000, 90, 10, None);
+        assert_eq!(winds.bands[&20000].len(), 1);
+        assert_eq!(winds.get_band(20000).unwrap().observations, 1);
+    }
+
+    #[test]
+    fn signs_tailwinds() {
+        let wind = WindEstimate {
+            direction: 270.0,
+            speed: 50.0,
+        };
+
+        // A west wind pushes eastbound traffic along
+        assert_close(wind.get_tailwind(90.0), 50.0);
+        assert_close(wind.get_tailwind(270.0), -50.0);
+        assert_close(wind.get_tailwind(0.0), 0.0);
+    }
+}